@@ -0,0 +1,263 @@
+// How fast log lines are cut up, the way it used to be done (a String per
+// field, filled a char at a time) against the borrowed ParsedLine and Fields.
+//
+//     $ cargo bench --bench parsing
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use nginx_tail::format::Format;
+use nginx_tail::json::JsonKeys;
+use nginx_tail::parsing::parse_nginx_line;
+
+/// Combined lines with timings, as a custom log_format would have them
+fn lines() -> Vec<String> {
+    let urls = [
+        "/",
+        "/users/12/orders?page=3",
+        "/static/app.4f3e2a.js",
+        "/api/v2/stats",
+    ];
+    let statuscodes = ["200", "200", "200", "304", "404", "502"];
+    (0..1000)
+        .map(|i| {
+            format!(
+                r#"10.0.{}.{} - - [26/May/2025:00:00:{:02} +0200] "GET {} HTTP/1.1" {} {} "-" "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0" 0.{:03} 0.{:03}"#,
+                i % 7,
+                i % 251,
+                i % 60,
+                urls[i % urls.len()],
+                statuscodes[i % statuscodes.len()],
+                i * 37,
+                i % 1000,
+                i % 999,
+            )
+        })
+        .collect()
+}
+
+/// Lines per second of `parse` over the lines, repeated for about a second
+fn measure(lines: &[String], mut parse: impl FnMut(&str)) -> f64 {
+    let start = Instant::now();
+    let mut parsed = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        for line in lines {
+            parse(line);
+        }
+        parsed += lines.len();
+    }
+    parsed as f64 / start.elapsed().as_secs_f64()
+}
+
+fn report(name: &str, before: f64, after: f64) {
+    println!(
+        "{name:<24} {:>12.0} lines/s {:>12.0} lines/s {:>6.1}x",
+        before,
+        after,
+        after / before
+    );
+}
+
+#[allow(dead_code)] // only built to be thrown away
+struct OwnedLine {
+    head: String,
+    head_date: Option<String>,
+    date: String,
+    date_method: Option<String>,
+    method: String,
+    method_url: Option<String>,
+    url: String,
+    url_lvl: Option<String>,
+    protocollvl: String,
+    lvl_statuscode: Option<String>,
+    statuscode: String,
+    tail: String,
+}
+
+/// parse_nginx_line as it was
+fn parse_owned(line: &str) -> OwnedLine {
+    // Has to be able to parse a partial line!
+    // Take special consideration whether you've seen separator symbols:
+    let mut head = "".to_owned();
+    let mut head_date = None;
+    let mut date = "".to_owned();
+    let mut date_method = None;
+    let mut method = "".to_owned();
+    let mut method_url = None;
+    let mut url = "".to_owned();
+    let mut url_lvl = None;
+    let mut protocollvl = "".to_owned();
+    let mut lvl_statuscode = None;
+    let mut statuscode = "".to_owned();
+    let mut tail = "".to_owned();
+
+    // om nom nom
+    let mut chars = line.chars();
+    #[allow(clippy::never_loop)]
+    'outer: loop {
+        loop {
+            match chars.next() {
+                None => break 'outer,
+                Some('[') => break,
+                Some(chr) => head.push(chr),
+            }
+        }
+        head_date = Some("[".to_owned());
+
+        loop {
+            match chars.next() {
+                None => break 'outer,
+                Some(']') => break,
+                Some(chr) => date.push(chr),
+            }
+        }
+        date_method = Some("]".to_owned());
+        match chars.next() {
+            Some(' ') => date_method.as_mut().unwrap().push(' '),
+            Some(x) => {
+                tail.push(x);
+                break 'outer;
+            }
+            None => break 'outer,
+        }
+
+        loop {
+            match chars.next() {
+                None => break 'outer,
+                Some('"') => break,
+                Some(chr) => date_method.as_mut().unwrap().push(chr),
+            }
+        }
+        date_method.as_mut().unwrap().push('"');
+
+        loop {
+            match chars.next() {
+                None => break 'outer,
+                Some(' ') => break,
+                Some(chr) => method.push(chr),
+            }
+        }
+        method_url = Some(" ".to_owned());
+        loop {
+            match chars.next() {
+                None => break 'outer,
+                Some(' ') => break,
+                Some(chr) => url.push(chr),
+            }
+        }
+        url_lvl = Some(" ".to_owned());
+        loop {
+            match chars.next() {
+                None => break 'outer,
+                Some('"') => break,
+                Some(chr) => protocollvl.push(chr),
+            }
+        }
+        lvl_statuscode = Some("\"".to_owned());
+        match chars.next() {
+            Some(' ') => lvl_statuscode.as_mut().unwrap().push(' '),
+            Some(x) => {
+                tail.push(x);
+                break 'outer;
+            }
+            None => break 'outer,
+        }
+        loop {
+            match chars.next() {
+                None => break 'outer,
+                Some(' ') => {
+                    tail.push(' ');
+                    break;
+                }
+                Some(chr) => statuscode.push(chr),
+            }
+        }
+        break 'outer; // who said Rust didn't have goto ;-)
+    }
+    tail.extend(chars);
+    OwnedLine {
+        head,
+        head_date,
+        date,
+        date_method,
+        method,
+        method_url,
+        url,
+        url_lvl,
+        protocollvl,
+        lvl_statuscode,
+        statuscode,
+        tail,
+    }
+}
+
+/// Where extract_statuscode and friends found the status code
+fn locate_statuscode(line: &str) -> Option<(usize, usize)> {
+    let first_quote = line.find('"')?;
+    let second_quote = first_quote + 1 + line[first_quote + 1..].find('"')?;
+    let start = second_quote + 2;
+    let end = start + line.get(start..)?.find(' ')?;
+    Some((start, end))
+}
+
+/// The fields as they used to be extracted: every one on its own, the status
+/// code copied
+fn fields_owned(line: &str) -> (Option<String>, Option<u64>, Option<&str>, Option<f32>) {
+    let statuscode = locate_statuscode(line).map(|(start, end)| line[start..end].to_owned());
+    let bytes = locate_statuscode(line)
+        .and_then(|(_, end)| line[end + 1..].split(' ').next()?.parse().ok());
+    let url = line.find('"').and_then(|first_quote| {
+        let request = &line[first_quote + 1..];
+        let request = &request[..request.find('"').unwrap_or(request.len())];
+        request.split(' ').nth(1)
+    });
+    let request_time = locate_statuscode(line).and_then(|(_, end)| {
+        line[end..]
+            .split(' ')
+            .find(|x| {
+                x.split_once('.').is_some_and(|(seconds, millis)| {
+                    !seconds.is_empty()
+                        && seconds.bytes().all(|x| x.is_ascii_digit())
+                        && millis.len() == 3
+                        && millis.bytes().all(|x| x.is_ascii_digit())
+                })
+            })?
+            .parse()
+            .ok()
+    });
+    (statuscode, bytes, url, request_time)
+}
+
+fn main() {
+    let lines = lines();
+    println!("{:<24} {:>20} {:>20}", "", "before", "after");
+
+    let before = measure(&lines, |line| {
+        black_box(parse_owned(black_box(line)));
+    });
+    let after = measure(&lines, |line| {
+        black_box(parse_nginx_line(black_box(line)));
+    });
+    report("parse_nginx_line", before, after);
+
+    let before = measure(&lines, |line| {
+        black_box(fields_owned(black_box(line)));
+    });
+    let keys = JsonKeys::default();
+    let after = measure(&lines, |line| {
+        black_box(Format::CUSTOM.fields(black_box(line), &keys));
+    });
+    report("fields", before, after);
+
+    // what follow sends along with every line
+    let owned = "/var/log/nginx/sites/customer_project_0/access.log".to_owned();
+    let shared: Arc<str> = owned.as_str().into();
+    let before = measure(&lines, |_| {
+        black_box(owned.clone());
+    });
+    let after = measure(&lines, |_| {
+        black_box(shared.clone());
+    });
+    report("group per line", before, after);
+}
@@ -0,0 +1,100 @@
+// What it costs to get lines from a fast log through the channel into the
+// processor: a fake_fast-style generator writes 50k lines/s, either one message
+// per line or batched per read the way follow does it. Reported is the CPU time
+// of the executor thread, which runs both ends.
+//
+//     $ cargo bench --bench throughput
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use smol::channel::unbounded;
+use smol::{LocalExecutor, Timer, future};
+
+use nginx_tail::backlog::Backlog;
+use nginx_tail::filter::Filter;
+use nginx_tail::json::JsonKeys;
+use nginx_tail::routes::RouteNormaliser;
+use nginx_tail::{Line, SenderChannel, process_as_streaming};
+
+const LINES_PER_SECOND: usize = 50_000;
+const TICK: Duration = Duration::from_millis(10);
+const RUNTIME: Duration = Duration::from_secs(2);
+
+/// Nanoseconds this thread spent on the CPU
+fn cpu_time() -> u64 {
+    let schedstat = std::fs::read_to_string("/proc/thread-self/schedstat").unwrap();
+    schedstat.split(' ').next().unwrap().parse().unwrap()
+}
+
+/// Sends LINES_PER_SECOND for RUNTIME, in messages of `batch` lines
+async fn fake_fast(channel: SenderChannel, backlog: Backlog, batch: usize) {
+    let (group, statuscode): (Arc<str>, Arc<str>) = ("generator".into(), "200".into());
+    let per_tick = LINES_PER_SECOND * TICK.as_millis() as usize / 1000;
+    let start = Instant::now();
+    let mut tick = 0;
+    while start.elapsed() < RUNTIME {
+        tick += 1;
+        Timer::at(start + TICK * tick).await;
+        let mut lines = (0..per_tick).map(|i| Line {
+            text: format!(r#"10.0.0.{i} - - [26/May/2025:00:00:01 +0200] "GET /api/v2/stats HTTP/1.1" 200 {tick} "-" "curl/8""#),
+            statuscode: Some(statuscode.clone()),
+            bytes: Some(tick.into()),
+            url: None,
+            request_time: None,
+            dropped: false,
+        });
+        loop {
+            let lines: Vec<Line> = lines.by_ref().take(batch).collect();
+            if lines.is_empty() {
+                break;
+            }
+            if backlog.send(&channel, group.clone(), lines).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Share of a core the executor thread needed while sending in batches of `batch`
+fn measure(batch: usize) -> f64 {
+    let executor = LocalExecutor::new();
+    let (sender, receiver) = unbounded();
+    let backlog = Backlog::default();
+    executor
+        .spawn(fake_fast(sender, backlog.clone(), batch))
+        .detach();
+    // a filter nothing passes, so the processor doesn't spend its time printing
+    let filter = Filter::parse("599").unwrap();
+    let start = Instant::now();
+    let cpu = cpu_time();
+    future::block_on(executor.run(process_as_streaming(
+        receiver,
+        backlog,
+        filter,
+        RouteNormaliser::new(),
+        JsonKeys::default(),
+        false,
+        false,
+    )));
+    (cpu_time() - cpu) as f64 / start.elapsed().as_nanos() as f64
+}
+
+fn main() {
+    println!("{LINES_PER_SECOND} lines/s for {RUNTIME:?}");
+    let single = measure(1);
+    println!(
+        "{:<24} {:>6.1}% cpu",
+        "one message per line",
+        single * 100.0
+    );
+    for batch in [10, 100, 500] {
+        let batched = measure(batch);
+        println!(
+            "{:<24} {:>6.1}% cpu {:>6.1}x",
+            format!("batches of {batch}"),
+            batched * 100.0,
+            single / batched
+        );
+    }
+}
@@ -0,0 +1,149 @@
+// How many bytes of lines are waiting in the channel between follow and the
+// processor. The channel counts messages, but one message can hold a single
+// line or a thousand. When the processor can't keep up, fe. on a slow terminal,
+// the text of new lines is dropped but the lines are still sent so the stats
+// count every request. Only when even that isn't enough does the sender wait.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use smol::Timer;
+use smol::channel::{Receiver, RecvError, SendError};
+
+use crate::{Line, Message, SenderChannel};
+
+/// Above this the text of new lines is dropped
+pub const DEEP: usize = 32 * 1024 * 1024;
+/// Above this the sender waits for the processor to catch up
+pub const FULL: usize = 2 * DEEP;
+
+/// The bytes of lines in one channel, shared by both of its ends. Lines are
+/// added by `send` and taken off by `recv`, so those should be the only way
+/// Message::Lines goes through the channel.
+#[derive(Debug, Clone, Default)]
+pub struct Backlog(Arc<AtomicUsize>);
+
+impl Backlog {
+    pub fn queued(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Sends the lines as one Message::Lines, without their text when the
+    /// queue is deep
+    pub async fn send(
+        &self,
+        channel: &SenderChannel,
+        updowngroup: Arc<str>,
+        mut lines: Vec<Line>,
+    ) -> Result<(), SendError<Message>> {
+        if self.queued() >= DEEP {
+            // keep what the stats need
+            lines.iter_mut().for_each(drop_text);
+        }
+        while self.queued() >= FULL {
+            Timer::after(Duration::from_millis(10)).await;
+        }
+        self.0.fetch_add(size(&lines), Ordering::Relaxed);
+        channel.send(Message::Lines { updowngroup, lines }).await
+    }
+
+    /// The next message, lines that were sent are no longer queued
+    pub async fn recv(&self, channel: &Receiver<Message>) -> Result<Message, RecvError> {
+        let message = channel.recv().await?;
+        if let Message::Lines { lines, .. } = &message {
+            self.0.fetch_sub(size(lines), Ordering::Relaxed);
+        }
+        Ok(message)
+    }
+}
+
+/// The memory lines take while they wait
+fn size(lines: &[Line]) -> usize {
+    lines
+        .iter()
+        .map(|x| size_of::<Line>() + x.text.capacity())
+        .sum()
+}
+
+/// Drops all of the text except the url, which is needed for the routes
+fn drop_text(line: &mut Line) {
+    line.text = match line.url.take() {
+        Some(url) => {
+            let url = line.text[url].to_owned();
+            line.url = Some(0..url.len());
+            url
+        }
+        None => String::new(),
+    };
+    line.dropped = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backlog, DEEP, drop_text, size};
+    use crate::{Line, Message};
+    use smol::future;
+
+    fn line(text: &str, url: Option<std::ops::Range<usize>>) -> Line {
+        Line {
+            text: text.to_owned(),
+            statuscode: Some("200".into()),
+            bytes: Some(5),
+            url,
+            request_time: Some(0.1),
+            dropped: false,
+        }
+    }
+
+    #[test]
+    fn test_drop_text() {
+        let text = r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /a/b HTTP/1.1" 200 5"#;
+        let mut dropped = line(text, Some(46..50));
+        let before = size(std::slice::from_ref(&dropped));
+        drop_text(&mut dropped);
+        assert_eq!(dropped.text, "/a/b");
+        assert_eq!(dropped.url, Some(0..4));
+        assert!(dropped.dropped);
+        assert_eq!(dropped.statuscode.as_deref(), Some("200"));
+        assert!(size(std::slice::from_ref(&dropped)) < before);
+
+        let mut dropped = line(text, None);
+        drop_text(&mut dropped);
+        assert_eq!(dropped.text, "");
+    }
+
+    #[test]
+    fn test_backlog() {
+        future::block_on(async {
+            let backlog = Backlog::default();
+            let (sender, receiver) = smol::channel::unbounded();
+            backlog
+                .send(&sender, "a".into(), vec![line("/x", Some(0..2))])
+                .await
+                .unwrap();
+            assert!(backlog.queued() > 0);
+            assert_eq!(backlog.recv(&receiver).await.unwrap(), backlog_lines(false));
+            assert_eq!(backlog.queued(), 0);
+
+            // a deep queue gets lines without their text
+            backlog.0.store(DEEP, std::sync::atomic::Ordering::Relaxed);
+            backlog
+                .send(&sender, "a".into(), vec![line("/x", Some(0..2))])
+                .await
+                .unwrap();
+            assert_eq!(backlog.recv(&receiver).await.unwrap(), backlog_lines(true));
+            assert_eq!(backlog.queued(), DEEP);
+        });
+
+        fn backlog_lines(dropped: bool) -> Message {
+            Message::Lines {
+                updowngroup: "a".into(),
+                lines: vec![Line {
+                    dropped,
+                    ..line("/x", Some(0..2))
+                }],
+            }
+        }
+    }
+}
@@ -1,14 +1,21 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Instant, SystemTime};
 use std::{cmp, sync::Arc};
 
 use smol::lock::Mutex;
 
-use crate::{RingbufferSpeedometer, Speedometer as _};
+use crate::format::Format;
+use crate::parsing::SEVERITIES;
+use crate::speedometer::WeightedSpeedometer;
+use crate::{FileState, RingbufferSpeedometer, Speedometer as _, get_statuscode_class};
 
 pub struct StatusStats {
     pub statuscode: String,
     start: std::time::Instant,
-    pub pending: u32, // pending since start
+    pub pending: u32,       // pending since start
+    pub pending_bytes: u64, // pending since start
     pub ring: RingbufferSpeedometer,
+    pub bandwidth: WeightedSpeedometer,
 }
 
 impl StatusStats {
@@ -17,7 +24,9 @@ impl StatusStats {
             statuscode,
             start: std::time::Instant::now(),
             pending: 0,
+            pending_bytes: 0,
             ring: RingbufferSpeedometer::new(5),
+            bandwidth: WeightedSpeedometer::new(5),
         }
     }
     fn process(&mut self) {
@@ -26,8 +35,10 @@ impl StatusStats {
             return;
         }
         self.ring.add_measurement(elapsed, self.pending);
+        self.bandwidth.add(elapsed, self.pending_bytes);
         self.start = std::time::Instant::now();
         self.pending = 0;
+        self.pending_bytes = 0;
     }
 }
 
@@ -48,9 +59,52 @@ impl PartialEq for StatusStats {
 }
 impl Eq for StatusStats {}
 
+/// Upper bounds in seconds of the $request_time buckets, 1-2-5 steps. The
+/// last bucket holds everything slower.
+pub const LATENCY_BOUNDS: [f32; 13] = [
+    0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0,
+];
+pub const LATENCY_BUCKETS: usize = LATENCY_BOUNDS.len() + 1;
+
+/// Requests per $request_time bucket
+pub type LatencyColumn = [u32; LATENCY_BUCKETS];
+
+/// $request_time per bucket for every call to process, for the heatmap
+pub struct LatencyHistogram {
+    pending: LatencyColumn,           // since the last call to process
+    columns: VecDeque<LatencyColumn>, // oldest first
+}
+impl LatencyHistogram {
+    const MAX_COLUMNS: usize = 500; // wider than any screen
+
+    fn new() -> Self {
+        Self {
+            pending: [0; LATENCY_BUCKETS],
+            columns: VecDeque::new(),
+        }
+    }
+
+    pub fn add(&mut self, request_time: f32) {
+        let bucket = LATENCY_BOUNDS
+            .iter()
+            .position(|x| request_time < *x)
+            .unwrap_or(LATENCY_BOUNDS.len());
+        self.pending[bucket] += 1;
+    }
+
+    fn process(&mut self) {
+        if self.columns.len() == Self::MAX_COLUMNS {
+            self.columns.pop_front();
+        }
+        self.columns.push_back(std::mem::take(&mut self.pending));
+    }
+}
+
 pub struct GroupStats {
     pub group: String,
     pub stats: Vec<StatusStats>,
+    pub last_line: Option<Instant>, // as of the last call to process
+    pub latencies: LatencyHistogram,
     global_statuscodes: GlobalStatuscodes,
 }
 impl GroupStats {
@@ -58,35 +112,86 @@ impl GroupStats {
         Self {
             group,
             stats: vec![],
+            last_line: None,
+            latencies: LatencyHistogram::new(),
             global_statuscodes,
         }
     }
-    pub async fn get_or_create(&mut self, statuscode: String) -> &mut StatusStats {
+    pub async fn get_or_create(&mut self, statuscode: &str) -> &mut StatusStats {
         // we only max ~5 tags so looping is faster than a hashmap
         if let Some(index) = self.stats.iter().position(|x| x.statuscode == statuscode) {
             &mut self.stats[index]
         } else {
-            let mut globalstate = self.global_statuscodes.lock().await;
-            globalstate.push(statuscode.clone());
-            globalstate.sort();
-            globalstate.dedup();
-            self.stats.push(StatusStats::new(statuscode));
+            // error log severities get columns of their own, see view_severities
+            if get_statuscode_class(statuscode).is_some() {
+                let mut globalstate = self.global_statuscodes.lock().await;
+                globalstate.push(statuscode.to_owned());
+                globalstate.sort();
+                globalstate.dedup();
+            }
+            self.stats.push(StatusStats::new(statuscode.to_owned()));
             self.stats.sort();
             self.stats.last_mut().unwrap()
         }
     }
     pub fn process(&mut self) {
+        if self.stats.iter().any(|x| x.pending > 0) {
+            self.last_line = Some(Instant::now());
+        }
         for statusstats in self.stats.iter_mut() {
             statusstats.process();
         }
-    }
-    pub fn iter(&mut self) -> impl Iterator<Item = &StatusStats> {
-        self.stats.iter()
+        self.latencies.process();
     }
 }
 
 type GlobalStatuscodes = Arc<Mutex<Vec<String>>>;
 
+/// The speeds of one or more StatusStats added together, see GroupMap::view
+#[derive(Clone)]
+pub struct StatusView {
+    pub statuscode: String,
+    pub speed: f32,
+    pub bandwidth: f32,
+}
+
+pub struct GroupView {
+    pub group: String,
+    pub stats: Vec<StatusView>,     // sorted by statuscode
+    pub last_line: Option<Instant>, // the most recent of the folded groups
+}
+impl GroupView {
+    fn add(&mut self, statuscode: String, speed: f32, bandwidth: f32) {
+        match self
+            .stats
+            .binary_search_by(|x| x.statuscode.as_str().cmp(&statuscode))
+        {
+            Ok(index) => {
+                self.stats[index].speed += speed;
+                self.stats[index].bandwidth += bandwidth;
+            }
+            Err(index) => self.stats.insert(
+                index,
+                StatusView {
+                    statuscode,
+                    speed,
+                    bandwidth,
+                },
+            ),
+        }
+    }
+}
+
+fn bucket(statuscode: &str, merge: bool) -> String {
+    match merge {
+        true => get_statuscode_class(statuscode).unwrap_or_else(|| statuscode.to_owned()),
+        false => statuscode.to_owned(),
+    }
+}
+
+/// The group of the routes that came after the first ones, see route_group
+pub const OTHER_ROUTES: &str = "other";
+
 pub struct GroupMap {
     pub stats: Vec<GroupStats>,
     pub shared_prefix: String,
@@ -102,22 +207,124 @@ impl GroupMap {
             global_statuscodes,
         }
     }
-    pub fn get_or_create(&mut self, tag: String) -> &mut GroupStats {
-        // we only expect a max of ~5 groups so looping is faster than a hashmap
+    pub fn get_or_create(&mut self, tag: &str) -> &mut GroupStats {
+        // we only expect a max of ~5 files, and the routes are capped by
+        // route_group, so looping is faster than a hashmap
         if let Some(index) = self.stats.iter().position(|x| x.group == tag) {
             &mut self.stats[index]
         } else {
-            self.stats
-                .push(GroupStats::new(tag, self.global_statuscodes.clone()));
+            self.stats.push(GroupStats::new(
+                tag.to_owned(),
+                self.global_statuscodes.clone(),
+            ));
             self.update_trimmed_tags();
             self.stats.last_mut().unwrap()
         }
     }
 
+    /// The group to count a route in: a site can have a route per page, so
+    /// once there are `max` groups new routes all end up in "other"
+    pub fn route_group<'a>(&self, route: &'a str, max: usize) -> &'a str {
+        if self.stats.len() < max || self.stats.iter().any(|x| x.group == route) {
+            route
+        } else {
+            OTHER_ROUTES
+        }
+    }
+
+    #[allow(unused)]
     pub fn len(&self) -> usize {
         self.stats.len()
     }
 
+    /// The stats are always kept per group and per status code, this folds
+    /// them into what we want to show: with `combine` all groups end up in a
+    /// single nameless group, with `merge` the status codes are bucketed by
+    /// their class (2xx, 3xx, ...)
+    pub fn view(&self, combine: bool, merge: bool) -> Vec<GroupView> {
+        let mut views: Vec<GroupView> = vec![];
+        for groupstats in self.stats.iter() {
+            let group = match combine {
+                true => "".to_owned(),
+                false => groupstats.group.clone(),
+            };
+            let view = match views.iter().position(|x| x.group == group) {
+                Some(index) => &mut views[index],
+                None => {
+                    views.push(GroupView {
+                        group,
+                        stats: vec![],
+                        last_line: None,
+                    });
+                    views.last_mut().unwrap()
+                }
+            };
+            view.last_line = cmp::max(view.last_line, groupstats.last_line);
+            for statusstats in groupstats.stats.iter() {
+                view.add(
+                    bucket(&statusstats.statuscode, merge),
+                    statusstats.ring.get_speed(),
+                    statusstats.bandwidth.per_second(),
+                );
+            }
+        }
+        views
+    }
+
+    /// The latency histogram of a group, or of all groups added together.
+    /// Groups created later have fewer columns, the columns line up at the
+    /// most recent one.
+    pub fn latencies(&self, group: Option<&str>) -> Vec<LatencyColumn> {
+        let mut columns: Vec<LatencyColumn> = vec![];
+        for groupstats in self.stats.iter() {
+            if group.is_some_and(|x| x != groupstats.group) {
+                continue;
+            }
+            let theirs = &groupstats.latencies.columns;
+            if theirs.len() > columns.len() {
+                let missing = theirs.len() - columns.len();
+                columns.splice(0..0, std::iter::repeat_n([0; LATENCY_BUCKETS], missing));
+            }
+            let offset = columns.len() - theirs.len();
+            for (column, their) in columns[offset..].iter_mut().zip(theirs) {
+                for (count, their) in column.iter_mut().zip(their) {
+                    *count += their;
+                }
+            }
+        }
+        columns
+    }
+
+    /// The severities of the error logs, and anything else that isn't a
+    /// status code, least severe first
+    pub fn view_severities(&self) -> Vec<String> {
+        let mut severities: Vec<&str> = self
+            .stats
+            .iter()
+            .flat_map(|x| x.stats.iter())
+            .map(|x| x.statuscode.as_str())
+            .filter(|x| get_statuscode_class(x).is_none())
+            .collect();
+        let rank = |x: &str| SEVERITIES.iter().position(|severity| *severity == x);
+        // anything unknown comes after the severities
+        severities.sort_by_key(|x| (rank(x).unwrap_or(SEVERITIES.len()), *x));
+        severities.dedup();
+        severities.into_iter().map(String::from).collect()
+    }
+
+    /// All HTTP status codes of all groups as they would show up in the view, sorted
+    pub async fn view_statuscodes(&self, merge: bool) -> Vec<String> {
+        let mut statuscodes: Vec<String> = self
+            .global_statuscodes
+            .lock()
+            .await
+            .iter()
+            .map(|x| bucket(x, merge))
+            .collect();
+        statuscodes.dedup(); // the classes of a sorted list are still sorted
+        statuscodes
+    }
+
     #[allow(unused)]
     pub fn is_empty(&self) -> bool {
         self.stats.is_empty()
@@ -139,34 +346,27 @@ impl GroupMap {
         self.shared_prefix.clear();
         self.shared_suffix.clear();
 
-        let mut max_tag_length = 0;
+        let max_tag_length = self.stats.iter().map(|x| x.group.len()).max().unwrap_or(0);
 
         // shared strings can never be longer than the any of the tags, so we'll
-        // just use the first one to iterate over
-        'outer: for i in 0..self.stats[0].group.len() {
-            for tag in self.stats.iter() {
-                max_tag_length = cmp::max(max_tag_length, tag.group.len()); // while we're here, let's also find the max tag length
-                if tag.group.chars().nth(i) != self.stats[0].group.chars().nth(i) {
-                    break 'outer;
-                }
-            }
-            self.shared_prefix
-                .push(self.stats[0].group.chars().nth(i).unwrap());
-        }
-
-        'outer: for i in 0..self.stats[0].group.len() {
-            for tag in self.stats.iter() {
-                if tag.group.chars().nth_back(i) != self.stats[0].group.chars().nth_back(i) {
-                    break 'outer;
-                }
-            }
-            self.shared_suffix
-                .insert(0, self.stats[0].group.chars().nth_back(i).unwrap());
+        // just compare the others to the first one, in bytes
+        let first = &self.stats[0].group;
+        let (mut prefix, mut suffix) = (first.len(), first.len());
+        for tag in self.stats.iter().skip(1) {
+            let shared = |(x, y): (char, char)| (x == y).then_some(x.len_utf8());
+            let chars = first.chars().zip(tag.group.chars());
+            prefix = cmp::min(prefix, chars.map_while(shared).sum());
+            let chars = first.chars().rev().zip(tag.group.chars().rev());
+            suffix = cmp::min(suffix, chars.map_while(shared).sum());
         }
+        self.shared_prefix.push_str(&first[..prefix]);
+        self.shared_suffix.push_str(&first[first.len() - suffix..]);
 
         // we don't need to reduce the tags to nothing,
         // there's space on the screen for some text
-        let text_left = max_tag_length - self.shared_prefix.len() - self.shared_suffix.len();
+        let text_left = max_tag_length
+            .saturating_sub(self.shared_prefix.len())
+            .saturating_sub(self.shared_suffix.len());
         if text_left < 8 {
             if max_tag_length < 8 {
                 self.shared_prefix = "".to_owned();
@@ -174,16 +374,133 @@ impl GroupMap {
             } else {
                 // we do not alter the suffix: it's probably .log which we want to filter out
                 let chars_to_preserve = 8 - text_left;
-                let cut_from_prefix = cmp::max(0, self.shared_prefix.len() - chars_to_preserve);
-                self.shared_prefix = self.shared_prefix[..cut_from_prefix].to_owned();
+                let cut_from_prefix = self.shared_prefix.len().saturating_sub(chars_to_preserve);
+                let cut_from_prefix = self.shared_prefix.floor_char_boundary(cut_from_prefix);
+                self.shared_prefix.truncate(cut_from_prefix);
+            }
+        }
+    }
+}
+
+/// Hands out one shared copy per distinct text, for the handful of status
+/// codes that come by on every line
+#[derive(Default)]
+pub struct Interner(Vec<Arc<str>>);
+impl Interner {
+    pub fn get(&mut self, text: &str) -> Arc<str> {
+        // there are only a few, so looping is faster than a hashmap
+        match self.0.iter().find(|x| ***x == *text) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned: Arc<str> = text.into();
+                self.0.push(interned.clone());
+                interned
+            }
+        }
+    }
+}
+
+/// What follow told us about one of the files we're reading
+pub struct FileInfo {
+    pub file: String, // as registered
+    pub state: FileState,
+    pub mtime: Option<SystemTime>,
+    pub rotated_at: Option<Instant>,
+    pub format: Option<Format>, // None until detected
+}
+impl FileInfo {
+    pub fn new(file: String) -> Self {
+        Self {
+            file,
+            state: FileState::Ok,
+            mtime: None,
+            rotated_at: None,
+            format: None,
+        }
+    }
+
+    pub fn update(&mut self, state: FileState, mtime: Option<SystemTime>) {
+        if state == FileState::Rotated {
+            // it's an event rather than a state: follow sends Ok next
+            self.rotated_at = Some(Instant::now());
+        }
+        self.state = state;
+        self.mtime = mtime;
+    }
+}
+
+/// Extra details about a single group, only collected while it has the focus
+pub struct FocusStats {
+    pub group: String,
+    pub requests: u64,            // since focusing
+    latencies: VecDeque<f32>,     // the most recent $request_time values
+    routes: HashMap<String, u64>, // requests per route since focusing
+}
+impl FocusStats {
+    const MAX_LATENCIES: usize = 1000;
+
+    pub fn new(group: String) -> Self {
+        Self {
+            group,
+            requests: 0,
+            latencies: VecDeque::with_capacity(Self::MAX_LATENCIES),
+            routes: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, request_time: Option<f32>, route: Option<String>) {
+        self.requests += 1;
+        if let Some(request_time) = request_time {
+            if self.latencies.len() == Self::MAX_LATENCIES {
+                self.latencies.pop_front();
             }
+            self.latencies.push_back(request_time);
+        }
+        if let Some(route) = route {
+            *self.routes.entry(route).or_default() += 1;
+        }
+    }
+
+    pub fn latency_samples(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// Nearest-rank percentiles (0..=100) over the most recent latencies
+    pub fn latency_percentiles(&self, percentiles: &[u8]) -> Option<Vec<f32>> {
+        if self.latencies.is_empty() {
+            return None;
         }
+        let mut sorted: Vec<f32> = self.latencies.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        let result = percentiles
+            .iter()
+            .map(|x| {
+                let rank = (*x as usize * sorted.len()).div_ceil(100);
+                sorted[rank.clamp(1, sorted.len()) - 1]
+            })
+            .collect();
+        Some(result)
+    }
+
+    /// The busiest routes, busiest first
+    pub fn top_routes(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut routes: Vec<(&str, u64)> = self
+            .routes
+            .iter()
+            .map(|(route, count)| (route.as_str(), *count))
+            .collect();
+        routes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        routes.truncate(n);
+        routes
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::collections::GlobalStatuscodes;
+    use std::sync::Arc;
+
+    use crate::collections::{FocusStats, GlobalStatuscodes, Interner, LATENCY_BUCKETS};
+    use crate::speedometer::Speedometer as _;
 
     #[test]
     fn test_tagmap_with_short_tags() {
@@ -191,36 +508,202 @@ mod tests {
         assert!(tagmap.is_empty());
         assert_eq!(tagmap.len(), 0);
 
-        let tag1 = tagmap.get_or_create("200".to_owned());
+        let tag1 = tagmap.get_or_create("200");
         assert_eq!(tag1.group, "200");
         assert_eq!(tagmap.len(), 1);
         assert_eq!(tagmap.shared_prefix, "");
         assert_eq!(tagmap.shared_suffix, "");
 
-        let tag2 = tagmap.get_or_create("500".to_owned());
+        let tag2 = tagmap.get_or_create("500");
         assert_eq!(tag2.group, "500");
         assert_eq!(tagmap.len(), 2);
         assert_eq!(tagmap.shared_prefix, "");
         assert_eq!(tagmap.shared_suffix, "");
 
-        tagmap.get_or_create("404".to_owned());
+        tagmap.get_or_create("404");
         assert_eq!(tagmap.len(), 3);
         assert_eq!(tagmap.shared_prefix, "");
         assert_eq!(tagmap.shared_suffix, "");
 
         // reuse tag
-        tagmap.get_or_create("200".to_owned());
+        tagmap.get_or_create("200");
         assert_eq!(tagmap.len(), 3);
     }
 
+    #[test]
+    fn test_interner() {
+        let mut interner = Interner::default();
+        let first = interner.get("200");
+        assert!(Arc::ptr_eq(&first, &interner.get("200")));
+        assert!(!Arc::ptr_eq(&first, &interner.get("404")));
+        assert_eq!(&*interner.get("404"), "404");
+    }
+
+    #[test]
+    fn test_latencies() {
+        let mut groups = super::GroupMap::new(GlobalStatuscodes::default());
+        let first = groups.get_or_create("first");
+        first.latencies.add(0.0005); // below the first bound
+        first.latencies.add(0.023);
+        first.latencies.add(60.0); // above the last bound
+        first.process();
+        let second = groups.get_or_create("second");
+        second.latencies.add(0.021);
+        for groupstats in groups.iter_mut() {
+            groupstats.process();
+        }
+
+        let mut expected_first = [0; LATENCY_BUCKETS];
+        expected_first[0] = 1;
+        expected_first[5] = 1; // 20ms..50ms
+        expected_first[LATENCY_BUCKETS - 1] = 1;
+        let mut expected_second = [0; LATENCY_BUCKETS];
+        expected_second[5] = 1;
+        let empty = [0; LATENCY_BUCKETS];
+
+        assert_eq!(groups.latencies(Some("first")), vec![expected_first, empty]);
+        assert_eq!(groups.latencies(Some("second")), vec![expected_second]);
+        // the second group only has the most recent column
+        assert_eq!(
+            groups.latencies(None),
+            vec![expected_first, expected_second]
+        );
+        assert!(groups.latencies(Some("third")).is_empty());
+    }
+
+    #[test]
+    fn test_focusstats() {
+        let mut focus = FocusStats::new("site".to_owned());
+        assert_eq!(focus.latency_percentiles(&[50]), None);
+        for i in 1..=100 {
+            let route = match i % 3 {
+                0 => "/a",
+                _ => "/b",
+            };
+            focus.add(Some(i as f32 / 1000.0), Some(route.to_owned()));
+        }
+        focus.add(None, Some("/c".to_owned()));
+        focus.add(None, None);
+        assert_eq!(focus.requests, 102);
+        assert_eq!(focus.latency_samples(), 100);
+        assert_eq!(
+            focus.latency_percentiles(&[0, 50, 99, 100]),
+            Some(vec![0.001, 0.05, 0.099, 0.1])
+        );
+        assert_eq!(focus.top_routes(2), vec![("/b", 67), ("/a", 33)]);
+        assert_eq!(focus.top_routes(5).len(), 3);
+    }
+
+    #[test]
+    fn test_view() {
+        smol::block_on(async {
+            let mut groupmap = super::GroupMap::new(GlobalStatuscodes::default());
+            for (group, statuscode, msgs) in [
+                ("site1", "200", 10),
+                ("site1", "404", 2),
+                ("site2", "200", 5),
+                ("site2", "201", 1),
+                ("site2", "502", 3),
+            ] {
+                let statusstats = groupmap
+                    .get_or_create(group)
+                    .get_or_create(statuscode)
+                    .await;
+                statusstats.ring.add_measurement(1000, msgs);
+                statusstats.bandwidth.add(1000, msgs.into());
+            }
+
+            let summary = |combine, merge| -> Vec<(String, Vec<(String, f32)>)> {
+                groupmap
+                    .view(combine, merge)
+                    .into_iter()
+                    .map(|group| {
+                        let stats = group.stats.into_iter().map(|x| {
+                            assert_eq!(x.speed, x.bandwidth);
+                            (x.statuscode, x.speed)
+                        });
+                        (group.group, stats.collect())
+                    })
+                    .collect()
+            };
+            let s = |x: &str| x.to_owned();
+
+            assert_eq!(
+                summary(false, false),
+                vec![
+                    (s("site1"), vec![(s("200"), 10.0), (s("404"), 2.0)]),
+                    (
+                        s("site2"),
+                        vec![(s("200"), 5.0), (s("201"), 1.0), (s("502"), 3.0)]
+                    ),
+                ]
+            );
+            assert_eq!(
+                summary(false, true),
+                vec![
+                    (s("site1"), vec![(s("2xx"), 10.0), (s("4xx"), 2.0)]),
+                    (s("site2"), vec![(s("2xx"), 6.0), (s("5xx"), 3.0)]),
+                ]
+            );
+            assert_eq!(
+                summary(true, false),
+                vec![(
+                    s(""),
+                    vec![
+                        (s("200"), 15.0),
+                        (s("201"), 1.0),
+                        (s("404"), 2.0),
+                        (s("502"), 3.0)
+                    ]
+                )]
+            );
+            assert_eq!(
+                summary(true, true),
+                vec![(
+                    s(""),
+                    vec![(s("2xx"), 16.0), (s("4xx"), 2.0), (s("5xx"), 3.0)]
+                )]
+            );
+            assert_eq!(
+                groupmap.view_statuscodes(true).await,
+                vec!["2xx", "4xx", "5xx"]
+            );
+            assert_eq!(groupmap.view_statuscodes(false).await.len(), 4);
+        });
+    }
+
+    #[test]
+    fn test_severities() {
+        smol::block_on(async {
+            let mut groupmap = super::GroupMap::new(GlobalStatuscodes::default());
+            for (group, statuscode) in [
+                ("access.log", "200"),
+                ("error.log", "warn"),
+                ("error.log", "crit"),
+                ("error.log", "notice"),
+                ("generator", "slow"),
+            ] {
+                groupmap
+                    .get_or_create(group)
+                    .get_or_create(statuscode)
+                    .await;
+            }
+            // the access logs don't get a column per severity
+            assert_eq!(groupmap.view_statuscodes(false).await, vec!["200"]);
+            assert_eq!(
+                groupmap.view_severities(),
+                vec!["notice", "warn", "crit", "slow"]
+            );
+        });
+    }
+
     #[test]
     fn test_tagmap_with_long_tags() {
         let mut tagmap = super::GroupMap::new(GlobalStatuscodes::default());
         assert!(tagmap.is_empty());
         assert_eq!(tagmap.len(), 0);
 
-        let tag1 =
-            tagmap.get_or_create("/var/log/nginx/sites/customer_project_0/access.log".to_owned());
+        let tag1 = tagmap.get_or_create("/var/log/nginx/sites/customer_project_0/access.log");
         assert_eq!(
             tag1.group,
             "/var/log/nginx/sites/customer_project_0/access.log"
@@ -229,8 +712,7 @@ mod tests {
         assert_eq!(tagmap.shared_prefix, "");
         assert_eq!(tagmap.shared_suffix, "");
 
-        let tag2 =
-            tagmap.get_or_create("/var/log/nginx/sites/customer_project_1/access.log".to_owned());
+        let tag2 = tagmap.get_or_create("/var/log/nginx/sites/customer_project_1/access.log");
         assert_eq!(
             tag2.group,
             "/var/log/nginx/sites/customer_project_1/access.log"
@@ -239,22 +721,54 @@ mod tests {
         assert_eq!(tagmap.shared_prefix, "/var/log/nginx/sites/customer_p");
         assert_eq!(tagmap.shared_suffix, "/access.log");
 
-        tagmap.get_or_create("/var/log/nginx/sites/customer_project_2/access.log".to_owned());
+        tagmap.get_or_create("/var/log/nginx/sites/customer_project_2/access.log");
         assert_eq!(tagmap.len(), 3);
         assert_eq!(tagmap.shared_prefix, "/var/log/nginx/sites/customer_p");
         assert_eq!(tagmap.shared_suffix, "/access.log");
 
         // reuse tag
-        tagmap.get_or_create("/var/log/nginx/sites/customer_project_1/access.log".to_owned());
+        tagmap.get_or_create("/var/log/nginx/sites/customer_project_1/access.log");
         assert_eq!(tagmap.len(), 3);
         assert_eq!(tagmap.shared_prefix, "/var/log/nginx/sites/customer_p");
         assert_eq!(tagmap.shared_suffix, "/access.log");
 
         // like a "root" log file
-        let tag5 = tagmap.get_or_create("/var/log/nginx/sites/access.log".to_owned());
+        let tag5 = tagmap.get_or_create("/var/log/nginx/sites/access.log");
         assert_eq!(tag5.group, "/var/log/nginx/sites/access.log");
         assert_eq!(tagmap.len(), 4);
         assert_eq!(tagmap.shared_prefix, "/var/log/nginx/sites/");
         assert_eq!(tagmap.shared_suffix, "/access.log");
     }
+
+    #[test]
+    fn test_tagmap_with_wide_chars() {
+        let mut tagmap = super::GroupMap::new(GlobalStatuscodes::default());
+        tagmap.get_or_create("/var/log/nginx/café_the_first/access.log");
+        tagmap.get_or_create("/var/log/nginx/café_a_second/access.log");
+        assert_eq!(tagmap.shared_prefix, "/var/log/nginx/café_");
+        assert_eq!(tagmap.shared_suffix, "/access.log");
+
+        // the text that's kept starts halfway the é
+        let mut tagmap = super::GroupMap::new(GlobalStatuscodes::default());
+        tagmap.get_or_create("/var/log/nginx/cafébcdefgh/x.log");
+        tagmap.get_or_create("/var/log/nginx/caféijklmno/x.log");
+        assert_eq!(tagmap.shared_prefix, "/var/log/nginx/caf");
+        assert_eq!(tagmap.shared_suffix, "/x.log");
+    }
+
+    #[test]
+    fn test_route_group() {
+        let mut groups = super::GroupMap::new(GlobalStatuscodes::default());
+        for route in ["/a", "/b"] {
+            let group = groups.route_group(route, 2);
+            assert_eq!(group, route);
+            groups.get_or_create(group);
+        }
+        // full, only the routes that are there already keep their own group
+        assert_eq!(groups.route_group("/a", 2), "/a");
+        assert_eq!(groups.route_group("/c", 2), super::OTHER_ROUTES);
+        groups.get_or_create(super::OTHER_ROUTES);
+        assert_eq!(groups.route_group("/d", 2), super::OTHER_ROUTES);
+        assert_eq!(groups.len(), 3);
+    }
 }
@@ -0,0 +1,361 @@
+// The dedup mode: lines that only differ in fields that change on every
+// request are shown once with a repeat count, like `uniq -c`. The TUI merges
+// them in the Sampler, streaming output collapses them as they go by.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::format::Format;
+use crate::json::{self, Field, JsonKeys};
+use crate::parsing::parse_error_line;
+use crate::routes::RouteNormaliser;
+use crate::{extract_url, is_timing};
+
+/// The line with its volatile fields masked: the timestamp, the port of the
+/// client, timings and whatever the RouteNormaliser replaces in the URL.
+/// `format` is the one of the file, when it's known.
+pub fn dedup_key(
+    line: &str,
+    format: Option<Format>,
+    normaliser: &RouteNormaliser,
+    keys: &JsonKeys,
+) -> String {
+    if let Some(format) = format
+        && let Some(volatile) = format.volatile(line)
+    {
+        let url = format.fields(line, keys).url;
+        return masked_key(line, volatile, url, normaliser);
+    }
+
+    if let Some(error_line) = parse_error_line(line) {
+        // without the date, process and connection
+        let mut key = format!("[{}] {}", error_line.level, error_line.message);
+        for (name, value) in error_line.context {
+            key += &format!(", {name}: {value}");
+        }
+        return key;
+    }
+
+    if let Some(members) = json::Members::new(line) {
+        return json_dedup_key(line, members, normaliser, keys);
+    }
+
+    let mut key = String::with_capacity(line.len());
+
+    // "1.2.3.4:5678 - - [..." when the format logs $remote_port as well
+    let (client, mut rest) = line.split_once(' ').unwrap_or((line, ""));
+    match client.rsplit_once(':') {
+        Some((address, port))
+            if address.contains('.')
+                && !port.is_empty()
+                && port.bytes().all(|x| x.is_ascii_digit()) =>
+        {
+            key += address
+        }
+        _ => key += client,
+    }
+    key.push(' ');
+
+    if let (Some(start), Some(end)) = (rest.find('['), rest.find(']'))
+        && start < end
+    {
+        key += &rest[..=start];
+        rest = &rest[end..];
+    }
+
+    if let Some(url) = extract_url(rest) {
+        // the URL is a slice of rest
+        let start = url.as_ptr() as usize - rest.as_ptr() as usize;
+        key += &rest[..start];
+        key += &normaliser.normalise(url);
+        rest = &rest[start + url.len()..];
+    }
+
+    for (index, field) in rest.split(' ').enumerate() {
+        if index > 0 {
+            key.push(' ');
+        }
+        match is_timing(field.as_bytes()) {
+            true => key.push('-'),
+            false => key += field,
+        }
+    }
+    key
+}
+
+/// The line with the volatile ranges as "-" and the URL normalised
+fn masked_key(
+    line: &str,
+    volatile: Vec<Range<usize>>,
+    url: Option<Range<usize>>,
+    normaliser: &RouteNormaliser,
+) -> String {
+    let mut replacements: Vec<(Range<usize>, String)> =
+        volatile.into_iter().map(|x| (x, "-".to_owned())).collect();
+    if let Some(url) = url {
+        let normalised = normaliser.normalise(&line[url.clone()]);
+        replacements.push((url, normalised));
+    }
+    replacements.sort_by_key(|(range, _)| range.start);
+
+    let mut key = String::with_capacity(line.len());
+    let mut written = 0;
+    for (range, replacement) in replacements {
+        if range.start < written {
+            // overlaps the one before
+            continue;
+        }
+        key += &line[written..range.start];
+        key += &replacement;
+        written = range.end;
+    }
+    key += &line[written..];
+    key
+}
+
+/// Keys of JSON lines that differ between otherwise equal requests
+const VOLATILE_KEYS: [&str; 10] = [
+    "time_local",
+    "time_iso8601",
+    "msec",
+    "remote_port",
+    "request_id",
+    "connection",
+    "connection_requests",
+    "upstream_connect_time",
+    "upstream_header_time",
+    "upstream_response_time",
+];
+
+/// The members of the object minus the volatile ones, with normalised URLs
+fn json_dedup_key(
+    line: &str,
+    members: json::Members,
+    normaliser: &RouteNormaliser,
+    keys: &JsonKeys,
+) -> String {
+    let mut key = String::with_capacity(line.len());
+    for member in members {
+        let name = json::key(line, &member);
+        let value = &line[member.value];
+        let field = keys.field(name);
+        if VOLATILE_KEYS.contains(&name) || field == Some(Field::RequestTime) {
+            continue;
+        }
+        key += name;
+        key.push('=');
+        match field {
+            Some(Field::RequestUri) => key += &normaliser.normalise(value.trim_matches('"')),
+            Some(Field::Request) => {
+                let request: Vec<&str> = value.trim_matches('"').split(' ').collect();
+                for (index, part) in request.iter().enumerate() {
+                    match index {
+                        0 => key += part,
+                        1 => key += &format!(" {}", normaliser.normalise(part)),
+                        _ => key += &format!(" {part}"),
+                    }
+                }
+            }
+            _ => key += value,
+        }
+        key.push(' ');
+    }
+    key
+}
+
+/// The line prefixed with how often it was seen, as `uniq -c` does
+pub fn with_count(line: &str, count: u64) -> String {
+    format!("{count:>7} {line}")
+}
+
+/// A line and how often it came by
+#[derive(Debug)]
+pub struct Repeated {
+    pub text: String,
+    pub statuscode: Option<Arc<str>>,
+    pub count: u64,
+}
+
+/// Collapses lines that follow each other and have the same dedup_key
+#[derive(Default)]
+pub struct Repeats {
+    held: Option<(String, Repeated)>, // the key and the first line with it
+}
+impl Repeats {
+    /// Returns the previous line once a different one comes by
+    pub fn add(
+        &mut self,
+        key: String,
+        text: String,
+        statuscode: Option<Arc<str>>,
+    ) -> Option<Repeated> {
+        if let Some((held_key, repeated)) = self.held.as_mut()
+            && *held_key == key
+        {
+            repeated.count += 1;
+            return None;
+        }
+        let repeated = Repeated {
+            text,
+            statuscode,
+            count: 1,
+        };
+        self.held.replace((key, repeated)).map(|(_, x)| x)
+    }
+
+    /// The line being counted, a repeat of it will be shown again
+    pub fn flush(&mut self) -> Option<Repeated> {
+        self.held.take().map(|(_, x)| x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Repeats, dedup_key, with_count};
+    use crate::format::Format;
+    use crate::json::JsonKeys;
+    use crate::routes::RouteNormaliser;
+
+    /// The dedup keys of lines in `format`
+    fn keys<const N: usize>(format: Format, lines: [&str; N]) -> [String; N] {
+        let normaliser = RouteNormaliser::new();
+        lines.map(|x| dedup_key(x, Some(format), &normaliser, &JsonKeys::default()))
+    }
+
+    #[test]
+    fn test_dedup_key() {
+        let normaliser = RouteNormaliser::new();
+        let keys = JsonKeys::default();
+        let first = r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /users/12/orders?page=1 HTTP/1.1" 200 63 "-" "curl/8" 0.023 0.022"#;
+        let second = r#"1.2.3.4 - - [26/May/2025:00:00:07 +0200] "GET /users/13/orders HTTP/1.1" 200 63 "-" "curl/8" 0.101 0.100"#;
+        let key = r#"1.2.3.4 - - [] "GET /users/:int/orders HTTP/1.1" 200 63 "-" "curl/8" - -"#;
+        assert_eq!(dedup_key(first, None, &normaliser, &keys), key);
+        assert_eq!(dedup_key(second, None, &normaliser, &keys), key);
+
+        // with $remote_port
+        assert_eq!(
+            dedup_key(
+                "1.2.3.4:51234 - - [x] \"GET / HTTP/1.1\" 404 0",
+                None,
+                &normaliser,
+                &keys
+            ),
+            "1.2.3.4 - - [] \"GET / HTTP/1.1\" 404 0"
+        );
+        // a different status code is a different line
+        assert_ne!(
+            dedup_key(&first.replace(" 200 ", " 500 "), None, &normaliser, &keys),
+            key
+        );
+        // error log lines
+        assert_eq!(
+            dedup_key(
+                "2024/01/01 12:00:00 [warn] 12#0: *345 an upstream response is buffered, client: 1.2.3.4",
+                None,
+                &normaliser,
+                &keys
+            ),
+            "[warn] an upstream response is buffered, client: 1.2.3.4"
+        );
+        // JSON lines
+        let json = r#"{"time_local": "26/May/2025:00:00:01 +0200", "request": "GET /users/12 HTTP/1.1", "status": 200, "request_time": 0.023}"#;
+        assert_eq!(
+            dedup_key(json, None, &normaliser, &keys),
+            "request=GET /users/:int HTTP/1.1 status=200 "
+        );
+        // anything unparseable is kept as is
+        assert_eq!(dedup_key("garbage", None, &normaliser, &keys), "garbage ");
+    }
+
+    #[test]
+    fn test_dedup_caddy() {
+        let [first, second, other] = keys(
+            Format::CADDY,
+            [
+                r#"{"level":"info","ts":1646861401.52,"logger":"http.log.access.log0","msg":"handled request","request":{"remote_ip":"127.0.0.1","remote_port":"41342","method":"GET","host":"localhost","uri":"/users/12"},"duration":0.000929675,"size":10900,"status":200}"#,
+                r#"{"level":"info","ts":1646861403.07,"logger":"http.log.access.log0","msg":"handled request","request":{"remote_ip":"127.0.0.1","remote_port":"41350","method":"GET","host":"localhost","uri":"/users/13"},"duration":0.001203311,"size":10900,"status":200}"#,
+                r#"{"level":"info","ts":1646861403.07,"logger":"http.log.access.log0","msg":"handled request","request":{"remote_ip":"127.0.0.1","remote_port":"41350","method":"GET","host":"localhost","uri":"/users/13"},"duration":0.001203311,"size":10900,"status":503}"#,
+            ],
+        );
+        assert_eq!(
+            first,
+            r#"{"level":"info","ts":-,"logger":"http.log.access.log0","msg":"handled request","request":{"remote_ip":"127.0.0.1","remote_port":-,"method":"GET","host":"localhost","uri":"/users/:int"},"duration":-,"size":10900,"status":200}"#
+        );
+        assert_eq!(first, second);
+        assert_ne!(second, other);
+    }
+
+    #[test]
+    fn test_dedup_traefik() {
+        let [first, second, other] = keys(
+            Format::TRAEFIK_JSON,
+            [
+                r#"{"ClientAddr":"192.168.1.7:52130","ClientHost":"192.168.1.7","DownstreamStatus":404,"Duration":2589000,"RequestCount":41,"RequestPath":"/api/users/12","StartUTC":"2023-10-10T13:55:36.1Z"}"#,
+                r#"{"ClientAddr":"192.168.1.7:52131","ClientHost":"192.168.1.7","DownstreamStatus":404,"Duration":1893000,"RequestCount":42,"RequestPath":"/api/users/7","StartUTC":"2023-10-10T13:55:37.4Z"}"#,
+                r#"{"ClientAddr":"192.168.1.7:52131","ClientHost":"192.168.1.7","DownstreamStatus":200,"Duration":1893000,"RequestCount":42,"RequestPath":"/api/users/7","StartUTC":"2023-10-10T13:55:37.4Z"}"#,
+            ],
+        );
+        assert_eq!(first, second);
+        assert_ne!(second, other);
+
+        let [first, second, other] = keys(
+            Format::TRAEFIK_CLF,
+            [
+                r#"192.168.1.7 - - [10/Oct/2023:13:55:36 +0000] "GET /api/users/12 HTTP/1.1" 502 1234 "-" "curl/8.1" 41 "api@docker" "http://10.0.0.2:80" 87ms"#,
+                r#"192.168.1.7 - - [10/Oct/2023:13:55:38 +0000] "GET /api/users/7 HTTP/1.1" 502 1234 "-" "curl/8.1" 42 "api@docker" "http://10.0.0.2:80" 3ms"#,
+                r#"192.168.1.7 - - [10/Oct/2023:13:55:38 +0000] "GET /api/users/7 HTTP/1.1" 502 1234 "-" "curl/8.1" 42 "web@docker" "http://10.0.0.3:80" 3ms"#,
+            ],
+        );
+        assert_eq!(
+            first,
+            r#"192.168.1.7 - - [-] "GET /api/users/:int HTTP/1.1" 502 1234 "-" "curl/8.1" - "api@docker" "http://10.0.0.2:80" -"#
+        );
+        assert_eq!(first, second);
+        assert_ne!(second, other);
+    }
+
+    #[test]
+    fn test_dedup_haproxy() {
+        let [first, second, other] = keys(
+            Format::HAPROXY,
+            [
+                r#"Feb  6 12:14:14 localhost haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu} {} "GET /users/1 HTTP/1.1""#,
+                r#"Feb  6 12:14:15 localhost haproxy[14389]: 10.0.1.2:33318 [06/Feb/2009:12:14:15.012] http-in static/srv1 8/0/25/51/84 200 2750 - - ---- 3/3/2/2/0 0/1 {1wt.eu} {} "GET /users/2 HTTP/1.1""#,
+                r#"Feb  6 12:14:15 localhost haproxy[14389]: 10.0.1.2:33318 [06/Feb/2009:12:14:15.012] http-in static/srv2 8/0/25/51/84 200 2750 - - ---- 3/3/2/2/0 0/1 {1wt.eu} {} "GET /users/2 HTTP/1.1""#,
+            ],
+        );
+        assert_eq!(
+            first,
+            r#"- localhost haproxy[14389]: 10.0.1.2:- [-] http-in static/srv1 - 200 2750 - - ---- - - {1wt.eu} {} "GET /users/:int HTTP/1.1""#
+        );
+        assert_eq!(first, second);
+        assert_ne!(second, other);
+
+        // without the syslog header
+        let [first, second] = keys(
+            Format::HAPROXY,
+            [
+                "10.0.1.2:33320 [06/Feb/2009:12:14:15.001] http-in~ www/<NOSRV> -1/-1/-1/-1/5003 408 212 - - cR-- 2/2/0/0/0 0/0 \"<BADREQ>\"",
+                "10.0.1.2:33391 [06/Feb/2009:12:15:02.317] http-in~ www/<NOSRV> -1/-1/-1/-1/5001 408 212 - - cR-- 1/1/0/0/0 0/0 \"<BADREQ>\"",
+            ],
+        );
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_repeats() {
+        let mut repeats = Repeats::default();
+        let mut add = |key: &str, text: &str| {
+            repeats
+                .add(key.to_owned(), text.to_owned(), None)
+                .map(|x| with_count(&x.text, x.count))
+        };
+        assert_eq!(add("a", "a1"), None);
+        assert_eq!(add("a", "a2"), None);
+        assert_eq!(add("b", "b1"), Some("      2 a1".to_owned()));
+        assert_eq!(add("a", "a3"), Some("      1 b1".to_owned()));
+        let flushed = repeats.flush().unwrap();
+        assert_eq!((flushed.text.as_str(), flushed.count), ("a3", 1));
+        assert!(repeats.flush().is_none());
+    }
+}
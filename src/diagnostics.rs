@@ -0,0 +1,57 @@
+// Warnings and errors that happen while running. Writing them to stderr
+// would mess up the TUI, so they're sent as a Message::Diagnostic instead:
+// process_as_tui shows them, process_as_streaming writes them to stderr.
+
+use std::fmt::Display;
+use std::time::Instant;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub text: String,
+    pub at: Instant,
+}
+impl Diagnostic {
+    pub fn new(severity: Severity, text: impl Into<String>) -> Self {
+        Self {
+            severity,
+            text: text.into(),
+            at: Instant::now(),
+        }
+    }
+}
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity}: {}", self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, Severity};
+
+    #[test]
+    fn test_display() {
+        let texts: Vec<String> = [
+            Diagnostic::new(Severity::Warning, "first"),
+            Diagnostic::new(Severity::Error, "second"),
+        ]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+        assert_eq!(texts, vec!["warning: first", "error: second"]);
+        assert!(Severity::Info < Severity::Warning);
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt::Display;
+
+use crate::get_statuscode_class;
+
+/// Decides which lines get shown. The statistics are not affected.
+///
+/// A filter is a list of terms:
+/// * `404`, `4xx`: the status code starts with this (an `x` is a wildcard)
+/// * `route=/users/:int`: the route template (see RouteNormaliser) is exactly this,
+///   a trailing `*` matches any suffix
+///
+/// A line is shown when it matches any of the status terms and any of the route
+/// terms. Lines lacking a field (fe. no status code) are not filtered on it,
+/// neither are error log lines on their severity.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Filter {
+    statuscodes: Vec<String>,
+    routes: Vec<String>,
+}
+
+impl Filter {
+    /// Parses whitespace separated terms
+    pub fn parse(terms: &str) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for term in terms.split_whitespace() {
+            filter.add_term(term)?;
+        }
+        Ok(filter)
+    }
+
+    pub fn add_term(&mut self, term: &str) -> Result<(), String> {
+        if let Some(route) = term.strip_prefix("route=") {
+            if route.is_empty() {
+                return Err("Empty route in filter".to_owned());
+            }
+            self.routes.push(route.to_owned());
+            self.routes.sort();
+            self.routes.dedup();
+        } else {
+            let statuscode = term.trim_end_matches(['x', 'X']);
+            if !statuscode.bytes().all(|x| x.is_ascii_digit()) {
+                return Err(format!("Unknown filter {term:?}"));
+            }
+            self.statuscodes.push(statuscode.to_owned());
+            self.statuscodes.sort();
+            self.statuscodes.dedup();
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statuscodes.is_empty() && self.routes.is_empty()
+    }
+
+    /// Whether the route is needed to decide on a match; computing it isn't free
+    pub fn uses_routes(&self) -> bool {
+        !self.routes.is_empty()
+    }
+
+    pub fn matches(&self, statuscode: Option<&str>, route: Option<&str>) -> bool {
+        let statuscode_ok = match statuscode {
+            Some(statuscode)
+                if !self.statuscodes.is_empty() && get_statuscode_class(statuscode).is_some() =>
+            {
+                self.statuscodes
+                    .iter()
+                    .any(|x| statuscode.starts_with(x.as_str()))
+            }
+            _ => true,
+        };
+        let route_ok = match route {
+            Some(route) if !self.routes.is_empty() => {
+                self.routes.iter().any(|x| match x.strip_suffix('*') {
+                    Some(prefix) => route.starts_with(prefix),
+                    None => route == x,
+                })
+            }
+            _ => true,
+        };
+        statuscode_ok && route_ok
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let statuscodes = self.statuscodes.iter().map(|x| {
+            let wildcards = 3_usize.saturating_sub(x.len());
+            format!("{x}{}", "x".repeat(wildcards))
+        });
+        let routes = self.routes.iter().map(|x| format!("route={x}"));
+        let terms: Vec<String> = statuscodes.chain(routes).collect();
+        write!(f, "{}", terms.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::parse("").unwrap();
+        assert!(filter.is_empty());
+        assert!(filter.matches(Some("500"), Some("/")));
+
+        let filter = Filter::parse("404 5xx route=/api/* route=/users/:int").unwrap();
+        assert!(filter.uses_routes());
+        assert!(filter.matches(Some("404"), Some("/api/v1")));
+        assert!(filter.matches(Some("503"), Some("/users/:int")));
+        assert!(!filter.matches(Some("200"), Some("/api/v1")));
+        assert!(!filter.matches(Some("500"), Some("/users/:int/orders")));
+        assert!(filter.matches(None, None));
+        assert!(filter.matches(Some("error"), None));
+        assert_eq!(filter.to_string(), "404 5xx route=/api/* route=/users/:int");
+
+        assert!(Filter::parse("route=").is_err());
+        assert!(Filter::parse("teapot").is_err());
+    }
+}
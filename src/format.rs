@@ -0,0 +1,354 @@
+// The kind of log a file holds. follow looks at the first lines of a file to
+// tell, unless --format already said so, and gets the fields out of a line
+// the way that format has them. Every format is a LogFormat in a module of
+// its own, named after the software writing it.
+
+mod apache;
+mod caddy;
+mod haproxy;
+mod nginx;
+mod traefik;
+
+use std::cmp;
+use std::fmt::{Debug, Display};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use smol::io::AsyncReadExt as _;
+
+use crate::json::JsonKeys;
+
+/// How many lines are looked at before the format is settled
+const SAMPLE_LINES: usize = 10;
+
+/// What follow needs from a line, None for what the format doesn't have.
+/// Getting them doesn't allocate: they're slices or byte ranges of the line.
+#[derive(Debug, Default, PartialEq)]
+pub struct Fields<'a> {
+    pub statuscode: Option<&'a str>, // the severity for error logs
+    pub bytes: Option<u64>,          // of the response body
+    pub url: Option<Range<usize>>,   // byte range of the line
+    pub request_time: Option<f32>,   // seconds
+}
+
+/// A kind of log file
+pub trait LogFormat: Sync {
+    /// As used with --format
+    fn name(&self) -> &'static str;
+
+    /// Whether the line was written in this format
+    fn matches(&self, line: &str) -> bool;
+
+    /// The fields of the line, the default when it doesn't fit the format.
+    /// `keys` are for JSON logs without keys of their own.
+    fn fields<'a>(&self, line: &'a str, keys: &JsonKeys) -> Fields<'a>;
+
+    /// Byte ranges of what differs between otherwise equal requests, for
+    /// the dedup mode. None leaves it to the masking for nginx' formats.
+    fn volatile(&self, _line: &str) -> Option<Vec<Range<usize>>> {
+        None
+    }
+}
+
+/// One of the built-in LogFormats
+#[derive(Clone, Copy)]
+pub struct Format(&'static dyn LogFormat);
+impl Format {
+    pub const COMBINED: Format = Format(&nginx::Combined);
+    pub const COMMON: Format = Format(&nginx::Common);
+    pub const CUSTOM: Format = Format(&nginx::Custom);
+    pub const JSON: Format = Format(&nginx::Json);
+    pub const ERROR_LOG: Format = Format(&nginx::ErrorLog);
+    pub const VHOST_COMBINED: Format = Format(&apache::VhostCombined);
+    pub const CADDY: Format = Format(&caddy::Caddy);
+    pub const HAPROXY: Format = Format(&haproxy::Haproxy);
+    pub const TRAEFIK_CLF: Format = Format(&traefik::Clf);
+    pub const TRAEFIK_JSON: Format = Format(&traefik::Json);
+
+    /// In the order they're tried, a line can match the more general ones
+    /// further down as well
+    const ALL: [Format; 10] = [
+        Format::ERROR_LOG,
+        Format::CADDY,
+        Format::TRAEFIK_JSON,
+        Format::JSON,
+        Format::HAPROXY,
+        Format::VHOST_COMBINED,
+        Format::TRAEFIK_CLF,
+        Format::COMBINED,
+        Format::COMMON,
+        Format::CUSTOM,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|x| x.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|x| x.name()).collect();
+                format!(
+                    "Unknown format {name:?}, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// What a single line looks like, None when it's no log line we know
+    pub fn of_line(line: &str) -> Option<Format> {
+        Self::ALL.into_iter().find(|x| x.0.matches(line))
+    }
+
+    /// The format most of the lines have
+    pub fn detect<'a>(lines: impl IntoIterator<Item = &'a str>) -> Option<Format> {
+        let mut votes = [0; Self::ALL.len()];
+        for line in lines {
+            if let Some(format) = Self::of_line(line) {
+                votes[Self::ALL.iter().position(|x| *x == format).unwrap()] += 1;
+            }
+        }
+        // on a tie the first in ALL wins
+        let (index, count) = votes
+            .iter()
+            .enumerate()
+            .max_by_key(|(index, count)| (**count, cmp::Reverse(*index)))?;
+        (*count > 0).then_some(Self::ALL[index])
+    }
+
+    pub fn fields<'a>(&self, line: &'a str, keys: &JsonKeys) -> Fields<'a> {
+        self.0.fields(line, keys)
+    }
+
+    pub fn volatile(&self, line: &str) -> Option<Vec<Range<usize>>> {
+        self.0.volatile(line)
+    }
+}
+impl PartialEq for Format {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+impl Debug for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Format({})", self.name())
+    }
+}
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Byte range of `part`, which has to be a slice of `line`
+fn range_of(line: &str, part: &str) -> Range<usize> {
+    let start = part.as_ptr() as usize - line.as_ptr() as usize;
+    start..start + part.len()
+}
+
+/// The URL from a request like "GET /some/url HTTP/1.1"
+fn request_url(request: &str) -> Option<&str> {
+    request.split(' ').nth(1)
+}
+
+/// The formats given with --format, FILE=FORMAT or FORMAT for all files
+#[derive(Debug, Default)]
+pub struct Overrides(Vec<(Option<PathBuf>, Format)>);
+impl Overrides {
+    pub fn add(&mut self, text: &str) -> Result<(), String> {
+        let (file, name) = match text.rsplit_once('=') {
+            Some(("", _)) => return Err(format!("Empty file in {text:?}")),
+            Some((file, name)) => (Some(PathBuf::from(file)), name),
+            None => (None, text),
+        };
+        self.0.push((file, Format::parse(name)?));
+        Ok(())
+    }
+
+    /// The format for the file, a FILE matches the whole path or the end of it
+    pub fn get(&self, file: &Path) -> Option<Format> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(x, _)| x.as_ref().is_none_or(|x| file.ends_with(x)))
+            .map(|(_, format)| *format)
+    }
+
+    /// The FILEs that match none of the files
+    pub fn unused(&self, files: &[PathBuf]) -> Vec<&Path> {
+        self.0
+            .iter()
+            .filter_map(|(x, _)| x.as_deref())
+            .filter(|x| !files.iter().any(|file| file.ends_with(x)))
+            .collect()
+    }
+}
+
+/// The first whole lines of the file
+pub async fn first_lines(file: &Path) -> Vec<String> {
+    let Ok(mut handle) = smol::fs::File::open(file).await else {
+        return vec![];
+    };
+    let mut head = vec![0; 16 * 1024];
+    let mut read = 0;
+    while read < head.len() {
+        match handle.read(&mut head[read..]).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => read += n,
+        }
+    }
+    let whole = head[..read]
+        .iter()
+        .rposition(|x| *x == b'\n')
+        .map_or(0, |x| x + 1);
+    String::from_utf8_lossy(&head[..whole])
+        .lines()
+        .take(SAMPLE_LINES)
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The format of a file so far: given, or detected from the lines seen up to
+/// now. Once SAMPLE_LINES lines were seen it no longer changes.
+pub struct Detection {
+    pub format: Option<Format>,
+    samples: Option<Vec<String>>, // None once settled
+    keys: JsonKeys,
+}
+impl Detection {
+    pub fn new(format: Option<Format>, keys: JsonKeys) -> Self {
+        Self {
+            format,
+            samples: format.is_none().then(Vec::new),
+            keys,
+        }
+    }
+
+    /// Returns the format when these lines changed it
+    pub fn sample(&mut self, lines: &[String]) -> Option<Format> {
+        let samples = self.samples.as_mut()?;
+        let room = SAMPLE_LINES - samples.len();
+        samples.extend(lines.iter().take(room).cloned());
+        let detected = Format::detect(samples.iter().map(String::as_str));
+        if samples.len() == SAMPLE_LINES {
+            self.samples = None;
+        }
+        if detected == self.format {
+            return None;
+        }
+        self.format = detected;
+        detected
+    }
+
+    /// Lines are taken as they come until there's a format
+    pub fn fields<'a>(&self, line: &'a str) -> Fields<'a> {
+        match self.format.or_else(|| Format::of_line(line)) {
+            Some(format) => format.fields(line, &self.keys),
+            None => Fields::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{Detection, Format, Overrides};
+    use crate::json::JsonKeys;
+
+    const COMBINED: &str =
+        r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /a HTTP/1.1" 404 63 "-" "curl/8""#;
+    const CUSTOM: &str = r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /a HTTP/1.1" 502 63 "-" "curl/8" 0.023 0.022"#;
+    const JSON: &str = r#"{"status": "200", "body_bytes_sent": 12}"#;
+    const ERROR: &str = "2024/01/01 12:00:00 [crit] 12#0: *345 something broke";
+
+    #[test]
+    fn test_of_line() {
+        fn of_line(line: &str) -> Option<&'static str> {
+            Format::of_line(line).map(|x| x.name())
+        }
+        assert_eq!(of_line(COMBINED), Some("combined"));
+        assert_eq!(of_line(CUSTOM), Some("custom"));
+        assert_eq!(of_line(JSON), Some("json"));
+        assert_eq!(of_line(ERROR), Some("error_log"));
+        assert_eq!(
+            of_line(&format!("www.example.com:443 {COMBINED}")),
+            Some("vhost_combined")
+        );
+        assert_eq!(
+            of_line(r#"{"logger":"http.log.access","status":200}"#),
+            Some("caddy")
+        );
+        assert_eq!(of_line(r#"{"DownstreamStatus":200}"#), Some("traefik_json"));
+        assert_eq!(
+            of_line(&format!(
+                r#"{COMBINED} 7 "api@docker" "http://10.0.0.2:80" 3ms"#
+            )),
+            Some("traefik_clf")
+        );
+        assert_eq!(
+            of_line(
+                r#"10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 "GET / HTTP/1.1""#
+            ),
+            Some("haproxy")
+        );
+        assert_eq!(of_line("garbage"), None);
+        assert_eq!(of_line("{}"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Format::detect([]), None);
+        assert_eq!(Format::detect(["garbage"]), None);
+        assert_eq!(
+            Format::detect([COMBINED, CUSTOM, "garbage", CUSTOM]),
+            Some(Format::CUSTOM)
+        );
+
+        let mut detection = Detection::new(None, JsonKeys::default());
+        assert_eq!(detection.fields(COMBINED).statuscode, Some("404"));
+        assert_eq!(detection.sample(&[JSON.to_owned()]), Some(Format::JSON));
+        assert_eq!(detection.sample(&[JSON.to_owned()]), None);
+        assert_eq!(detection.fields(COMBINED).statuscode, None);
+        // settled after ten lines
+        assert_eq!(
+            detection.sample(&vec![ERROR.to_owned(); 20]),
+            Some(Format::ERROR_LOG)
+        );
+        assert_eq!(detection.sample(&vec![JSON.to_owned(); 20]), None);
+
+        let mut given = Detection::new(Some(Format::COMMON), JsonKeys::default());
+        assert_eq!(given.sample(&[JSON.to_owned()]), None);
+        assert_eq!(given.format, Some(Format::COMMON));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut overrides = Overrides::default();
+        assert_eq!(overrides.get(Path::new("/a/access.log")), None);
+        overrides.add("json").unwrap();
+        overrides.add("site1/access.log=combined").unwrap();
+        overrides.add("/b/error.log=error_log").unwrap();
+        assert_eq!(
+            overrides.get(Path::new("/var/log/site1/access.log")),
+            Some(Format::COMBINED)
+        );
+        assert_eq!(
+            overrides.get(Path::new("/b/error.log")),
+            Some(Format::ERROR_LOG)
+        );
+        assert_eq!(overrides.get(Path::new("/c/error.log")), Some(Format::JSON));
+        // whole path components only
+        assert_eq!(
+            overrides.get(Path::new("/var/log/othersite1/access.log")),
+            Some(Format::JSON)
+        );
+        let files = [PathBuf::from("/var/log/site1/access.log")];
+        assert_eq!(overrides.unused(&files), vec![Path::new("/b/error.log")]);
+
+        assert!(overrides.add("a=teapot").is_err());
+        assert!(overrides.add("=json").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+// Apache's access logs. Its combined format is the same as nginx' and its
+// common format the same as nginx' common, so those are handled there. What's
+// left is vhost_combined: the virtual host and port in front of combined.
+//
+//     LogFormat "%v:%p %h %l %u %t \"%r\" %>s %O \"%{Referer}i\" \"%{User-Agent}i\"" vhost_combined
+
+use super::nginx::{Combined, text_fields};
+use super::{Fields, LogFormat};
+use crate::json::JsonKeys;
+
+pub struct VhostCombined;
+impl LogFormat for VhostCombined {
+    fn name(&self) -> &'static str {
+        "vhost_combined"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        let Some((vhost, rest)) = line.split_once(' ') else {
+            return false;
+        };
+        let is_port = |x: &str| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit());
+        // the date comes after the client, ident and user; an IPv6 client
+        // in a plain combined line has one field less in front of it
+        vhost
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| is_port(port))
+            && rest.split(' ').nth(3).is_some_and(|x| x.starts_with('['))
+            && Combined.matches(rest)
+    }
+
+    fn fields<'a>(&self, line: &'a str, _: &JsonKeys) -> Fields<'a> {
+        // the vhost is in front of the quoted request, where it doesn't matter
+        text_fields(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VhostCombined;
+    use crate::format::LogFormat as _;
+    use crate::format::nginx::Combined;
+    use crate::json::JsonKeys;
+
+    const COMBINED: &str = r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326 "http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#;
+    const VHOST: &str = r#"www.example.com:443 127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 304 - "-" "curl/8""#;
+
+    #[test]
+    fn test_combined() {
+        assert!(Combined.matches(COMBINED));
+        let fields = Combined.fields(COMBINED, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("200"));
+        assert_eq!(fields.bytes, Some(2326));
+        assert_eq!(&COMBINED[fields.url.unwrap()], "/apache_pb.gif");
+        assert!(!VhostCombined.matches(COMBINED));
+        assert!(!VhostCombined.matches(&COMBINED.replace("127.0.0.1", "::1")));
+    }
+
+    #[test]
+    fn test_vhost_combined() {
+        assert!(VhostCombined.matches(VHOST));
+        let fields = VhostCombined.fields(VHOST, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("304"));
+        // %O is "-" when nothing was sent
+        assert_eq!(fields.bytes, None);
+        assert_eq!(&VHOST[fields.url.unwrap()], "/apache_pb.gif");
+    }
+}
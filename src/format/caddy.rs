@@ -0,0 +1,74 @@
+// Caddy's access logs: JSON with the request as a nested object.
+//
+//     {"level":"info","ts":1646861401.52,"logger":"http.log.access.log0",
+//      "msg":"handled request","request":{"remote_ip":"127.0.0.1",
+//      "method":"GET","host":"localhost","uri":"/","headers":{...}},
+//      "bytes_read":0,"duration":0.000929675,"size":10900,"status":200,...}
+
+use std::ops::Range;
+
+use super::{Fields, LogFormat};
+use crate::json::{self, JsonKeys, unquote};
+
+pub struct Caddy;
+impl LogFormat for Caddy {
+    fn name(&self) -> &'static str {
+        "caddy"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        json::member(line, "logger")
+            .is_some_and(|x| unquote(&line[x]).starts_with("http.log.access"))
+    }
+
+    fn fields<'a>(&self, line: &'a str, _: &JsonKeys) -> Fields<'a> {
+        let raw = |name| json::member(line, name).map(|x| &line[x]);
+        let url = json::member(line, "request").and_then(|request| {
+            let uri = json::member(&line[request.clone()], "uri")?;
+            // without the quotes
+            Some(request.start + uri.start + 1..request.start + uri.end - 1)
+        });
+        Fields {
+            statuscode: json::member(line, "status").map(|x| &line[x]),
+            bytes: raw("size").and_then(|x| x.parse().ok()),
+            url,
+            // seconds, unless duration_format was changed
+            request_time: raw("duration").and_then(|x| x.parse().ok()),
+        }
+    }
+
+    fn volatile(&self, line: &str) -> Option<Vec<Range<usize>>> {
+        let mut volatile = json::members_named(line, &["ts", "duration"]);
+        if let Some(request) = json::member(line, "request") {
+            let ports = json::members_named(&line[request.clone()], &["remote_port"]);
+            volatile.extend(
+                ports
+                    .into_iter()
+                    .map(|x| request.start + x.start..request.start + x.end),
+            );
+        }
+        Some(volatile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Caddy;
+    use crate::format::LogFormat as _;
+    use crate::json::JsonKeys;
+
+    const LINE: &str = r#"{"level":"info","ts":1646861401.5241024,"logger":"http.log.access.log0","msg":"handled request","request":{"remote_ip":"127.0.0.1","remote_port":"41342","proto":"HTTP/2.0","method":"GET","host":"localhost","uri":"/users/12?a=b","headers":{"User-Agent":["curl/7.82.0"],"Accept":["*/*"]}},"bytes_read":0,"user_id":"","duration":0.000929675,"size":10900,"status":503,"resp_headers":{"Server":["Caddy"]}}"#;
+
+    #[test]
+    fn test_caddy() {
+        assert!(Caddy.matches(LINE));
+        assert!(!Caddy.matches(r#"{"logger":"tls","msg":"certificate obtained"}"#));
+        assert!(!Caddy.matches(r#"{"status":200}"#));
+
+        let fields = Caddy.fields(LINE, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("503"));
+        assert_eq!(fields.bytes, Some(10900));
+        assert_eq!(&LINE[fields.url.unwrap()], "/users/12?a=b");
+        assert_eq!(fields.request_time, Some(0.000929675));
+    }
+}
@@ -0,0 +1,139 @@
+// HAProxy's HTTP log (option httplog), usually with a syslog header in front.
+//
+//     Feb  6 12:14:14 localhost haproxy[14389]: 10.0.1.2:33317
+//     [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 2750
+//     - - ---- 1/1/1/1/0 0/0 {1wt.eu} {} "GET /index.html HTTP/1.1"
+//
+// The timers are TR/Tw/Tc/Tr/Ta in milliseconds, -1 when the request didn't
+// get that far. Ta is the total time of the request. The connection counts
+// and queues after the termination state are taken when the request ends.
+
+use std::ops::Range;
+
+use super::{Fields, LogFormat, range_of, request_url};
+use crate::json::JsonKeys;
+
+/// The fields of a line, the counters are None when the line ends early
+struct HttpLog<'a> {
+    syslog_time: Option<&'a str>, // "Feb  6 12:14:14", None without a syslog header
+    client: &'a str,              // "10.0.1.2:33317"
+    date: &'a str,                // inside the brackets
+    timers: &'a str,
+    status: &'a str,
+    bytes: &'a str,
+    counters: Option<(&'a str, &'a str)>, // actconn/feconn/beconn/srv_conn/retries, srv_queue/backend_queue
+    request: &'a str,                     // the quoted request at the end, without the quotes
+}
+
+fn parse(line: &str) -> Option<HttpLog<'_>> {
+    // " [06/Feb/2009:12:14:14.655] ", the syslog header has a [pid] as well
+    let date = line
+        .match_indices(" [")
+        .map(|(x, _)| x + 2)
+        .find(|x| line.as_bytes().get(*x).is_some_and(u8::is_ascii_digit))?;
+    let before = &line[..date - 2];
+    let (header, client) = before.rsplit_once(' ').unwrap_or(("", before));
+    // up to the end of the time: "Feb  6 12:14:14" or "2009-02-06T12:14:14+01:00"
+    let syslog_time = header
+        .find(':')
+        .and_then(|x| Some(&header[..x + header[x..].find(' ')?]));
+    let date_end = date + line[date..].find("] ")?;
+    let mut fields = line[date_end + 2..].split(' ');
+    let _frontend = fields.next()?;
+    let _backend_server = fields.next()?;
+    let timers = fields.next()?;
+    let status = fields.next()?;
+    let bytes = fields.next()?;
+    let is_timer = |x: &str| x.trim_start_matches('-').parse::<u32>().is_ok();
+    if timers.split('/').count() < 4 || !timers.split('/').all(is_timer) || !is_timer(status) {
+        return None;
+    }
+    // past the captured cookies and the termination state
+    let counters = fields.nth(3).zip(fields.next());
+    let request = line.strip_suffix('"')?;
+    let request = &request[request.rfind('"')? + 1..];
+    Some(HttpLog {
+        syslog_time,
+        client,
+        date: &line[date..date_end],
+        timers,
+        status,
+        bytes,
+        counters,
+        request,
+    })
+}
+
+pub struct Haproxy;
+impl LogFormat for Haproxy {
+    fn name(&self) -> &'static str {
+        "haproxy"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        parse(line).is_some()
+    }
+
+    fn fields<'a>(&self, line: &'a str, _: &JsonKeys) -> Fields<'a> {
+        let Some(log) = parse(line) else {
+            return Fields::default();
+        };
+        // Ta, -1 for aborted requests
+        let total: Option<u32> = log.timers.rsplit('/').next().and_then(|x| x.parse().ok());
+        Fields {
+            statuscode: Some(log.status),
+            bytes: log.bytes.parse().ok(),
+            url: request_url(log.request).map(|x| range_of(line, x)),
+            request_time: total.map(|x| x as f32 / 1000.0),
+        }
+    }
+
+    fn volatile(&self, line: &str) -> Option<Vec<Range<usize>>> {
+        let log = parse(line)?;
+        let mut volatile = vec![range_of(line, log.date), range_of(line, log.timers)];
+        volatile.extend(log.syslog_time.map(|x| range_of(line, x)));
+        if let Some((_, port)) = log.client.rsplit_once(':') {
+            volatile.push(range_of(line, port));
+        }
+        if let Some((connections, queues)) = log.counters {
+            volatile.push(range_of(line, connections));
+            volatile.push(range_of(line, queues));
+        }
+        Some(volatile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Haproxy;
+    use crate::format::LogFormat as _;
+    use crate::json::JsonKeys;
+
+    const LINE: &str = r#"Feb  6 12:14:14 localhost haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu} {} "GET /index.html HTTP/1.1""#;
+
+    #[test]
+    fn test_haproxy() {
+        assert!(Haproxy.matches(LINE));
+        let fields = Haproxy.fields(LINE, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("200"));
+        assert_eq!(fields.bytes, Some(2750));
+        assert_eq!(&LINE[fields.url.unwrap()], "/index.html");
+        assert_eq!(fields.request_time, Some(0.109));
+
+        // without the syslog header, and a client that gave up
+        let aborted = r#"10.0.1.2:33320 [06/Feb/2009:12:14:15.001] http-in~ www/<NOSRV> -1/-1/-1/-1/5003 408 212 - - cR-- 2/2/0/0/0 0/0 "<BADREQ>""#;
+        assert!(Haproxy.matches(aborted));
+        let fields = Haproxy.fields(aborted, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("408"));
+        assert_eq!(fields.url, None);
+        assert_eq!(fields.request_time, Some(5.003));
+
+        // a TCP log has no status code
+        assert!(!Haproxy.matches(
+            "10.0.1.2:33313 [06/Feb/2009:12:12:51.443] fnt bck/srv1 0/0/5007 212 -- 0/0/0/0/3 0/0"
+        ));
+        assert!(!Haproxy.matches(
+            r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /a HTTP/1.1" 404 63 "-" "curl/8""#
+        ));
+    }
+}
@@ -0,0 +1,254 @@
+// nginx' own logs: access logs in the combined format, in the common format
+// that leaves out referer and user agent, in any other log_format, as JSON,
+// and the error log.
+
+use super::{Fields, LogFormat, range_of};
+use crate::json::{self, JsonKeys};
+use crate::parsing::parse_error_line;
+use crate::{extract_url, is_timing, locate_statuscode};
+
+/// What comes after $status in a text access log
+#[derive(Debug, PartialEq)]
+enum Layout {
+    Combined, // $body_bytes_sent "$http_referer" "$http_user_agent"
+    Common,   // $body_bytes_sent
+    Custom,   // anything else
+}
+
+/// The Layout of a line with "$request" and a $status after it
+fn layout(line: &str) -> Option<Layout> {
+    let status = locate_statuscode(line)?;
+    let after_request = line[..status.start].rfind('"')? + 1;
+    if line[after_request..status.start] != *" " {
+        return Some(Layout::Custom);
+    }
+    let (bytes, rest) = match line[status.end..].strip_prefix(' ') {
+        Some(rest) => rest.split_once(' ').unwrap_or((rest, "")),
+        None => return Some(Layout::Custom),
+    };
+    if bytes != "-" && (bytes.is_empty() || !bytes.bytes().all(|x| x.is_ascii_digit())) {
+        return Some(Layout::Custom);
+    }
+    if rest.is_empty() {
+        return Some(Layout::Common);
+    }
+    match is_referer_and_agent(rest) {
+        true => Some(Layout::Combined),
+        false => Some(Layout::Custom),
+    }
+}
+
+/// `"$http_referer" "$http_user_agent"`, skipping escaped quotes inside them
+pub(super) fn is_referer_and_agent(rest: &str) -> bool {
+    quoted_fields(rest, 2).is_some_and(str::is_empty)
+}
+
+/// What's left after `count` quoted fields separated by spaces
+pub(super) fn quoted_fields(mut rest: &str, count: usize) -> Option<&str> {
+    for _ in 0..count {
+        let quoted = rest.strip_prefix('"')?;
+        let mut end = quoted.find('"');
+        while let Some(quote) = end
+            && quoted[..quote].ends_with('\\')
+        {
+            end = quoted[quote + 1..].find('"').map(|x| quote + 1 + x);
+        }
+        let after = &quoted[end? + 1..];
+        rest = after.strip_prefix(' ').unwrap_or(after);
+    }
+    Some(rest)
+}
+
+/// The fields of the text formats, they all have "$request" $status
+pub(super) fn text_fields(line: &str) -> Fields<'_> {
+    let Some(status) = locate_statuscode(line) else {
+        return Fields::default();
+    };
+    let after = &line[status.end..];
+    Fields {
+        statuscode: Some(&line[status]),
+        bytes: after
+            .strip_prefix(' ')
+            .and_then(|x| x.split(' ').next()?.parse().ok()),
+        url: extract_url(line).map(|x| range_of(line, x)),
+        // $request_time isn't part of the combined format, but most custom
+        // formats add it somewhere after the status
+        // scanned as bytes, splitting the str is several times slower
+        request_time: after
+            .as_bytes()
+            .split(|x| *x == b' ')
+            .find(|x| is_timing(x))
+            .and_then(|x| std::str::from_utf8(x).ok()?.parse().ok()),
+    }
+}
+
+/// `log_format combined`, which is what Apache calls combined as well
+pub struct Combined;
+impl LogFormat for Combined {
+    fn name(&self) -> &'static str {
+        "combined"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        layout(line) == Some(Layout::Combined)
+    }
+
+    fn fields<'a>(&self, line: &'a str, _: &JsonKeys) -> Fields<'a> {
+        text_fields(line)
+    }
+}
+
+pub struct Common;
+impl LogFormat for Common {
+    fn name(&self) -> &'static str {
+        "common"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        layout(line) == Some(Layout::Common)
+    }
+
+    fn fields<'a>(&self, line: &'a str, _: &JsonKeys) -> Fields<'a> {
+        text_fields(line)
+    }
+}
+
+/// Any text log_format with "$request" and a $status after it
+pub struct Custom;
+impl LogFormat for Custom {
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        layout(line).is_some()
+    }
+
+    fn fields<'a>(&self, line: &'a str, _: &JsonKeys) -> Fields<'a> {
+        text_fields(line)
+    }
+}
+
+/// `log_format ... escape=json`, with the keys from --json-key
+pub struct Json;
+impl LogFormat for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        json::Members::new(line).is_some_and(|mut x| x.next().is_some())
+    }
+
+    fn fields<'a>(&self, line: &'a str, keys: &JsonKeys) -> Fields<'a> {
+        let values = json::Values::new(line, keys);
+        // the status and numbers have nothing to unescape, only quotes to drop
+        let unquoted = |x: Option<&'a str>| x.map(|x| x.trim_matches('"'));
+        Fields {
+            statuscode: unquoted(values.status),
+            bytes: unquoted(values.body_bytes_sent).and_then(|x| x.parse().ok()),
+            url: values.url().map(|x| range_of(line, x)),
+            request_time: unquoted(values.request_time).and_then(|x| x.parse().ok()),
+        }
+    }
+}
+
+pub struct ErrorLog;
+impl LogFormat for ErrorLog {
+    fn name(&self) -> &'static str {
+        "error_log"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        parse_error_line(line).is_some()
+    }
+
+    fn fields<'a>(&self, line: &'a str, _: &JsonKeys) -> Fields<'a> {
+        Fields {
+            statuscode: parse_error_line(line).map(|x| x.level),
+            ..Fields::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Combined, Common, Custom, ErrorLog, Json};
+    use crate::format::{Fields, LogFormat};
+    use crate::json::JsonKeys;
+
+    const COMBINED: &str =
+        r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /a HTTP/1.1" 404 63 "-" "curl/8 \"x\"""#;
+    const COMMON: &str = r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /a HTTP/1.1" 200 -"#;
+    const CUSTOM: &str = r#"v2 1.22.3.44 - - [26/May/2025:00:00:01 +0200] "GET /v2/stats?interval=hours HTTP/1.0" 200 63 - 0.023 0.022 "-" "UserAgent/123" "https" "some.domain.example""#;
+    const UPSTREAM: &str =
+        r#"[26/May/2025:00:00:01 +0200] "GET /a HTTP/1.1" upstream=10.0.0.1:80 301 0.023"#;
+    const JSON: &str = r#"{"request": "GET /links.json HTTP/1.1", "status": "404", "body_bytes_sent": 91, "request_time": "0.004"}"#;
+    const ERROR: &str = "2024/01/01 12:00:00 [crit] 12#0: *345 something broke";
+
+    #[test]
+    fn test_matches() {
+        assert!(Combined.matches(COMBINED));
+        assert!(!Combined.matches(COMMON));
+        assert!(!Combined.matches(CUSTOM));
+        assert!(Common.matches(COMMON));
+        assert!(!Common.matches(COMBINED));
+        assert!(Custom.matches(CUSTOM));
+        assert!(Custom.matches(UPSTREAM));
+        assert!(Custom.matches(COMBINED));
+        assert!(!Custom.matches(JSON));
+        assert!(Json.matches(JSON));
+        assert!(!Json.matches("{}"));
+        assert!(ErrorLog.matches(ERROR));
+        assert!(!ErrorLog.matches(COMBINED));
+    }
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(
+            Combined.fields(COMBINED, &JsonKeys::default()),
+            Fields {
+                statuscode: Some("404"),
+                bytes: Some(63),
+                url: Some(46..48),
+                request_time: None,
+            }
+        );
+        assert_eq!(Common.fields(COMMON, &JsonKeys::default()).bytes, None);
+        let fields = Custom.fields(CUSTOM, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("200"));
+        assert_eq!(fields.bytes, Some(63));
+        assert_eq!(&CUSTOM[fields.url.unwrap()], "/v2/stats?interval=hours");
+        assert_eq!(fields.request_time, Some(0.023));
+        let fields = Custom.fields(UPSTREAM, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("301"));
+        assert_eq!(fields.request_time, Some(0.023));
+        assert_eq!(
+            Custom
+                .fields(r#"[date] "GET / HTTP/1.1" 200 "#, &JsonKeys::default())
+                .bytes,
+            None
+        );
+
+        let fields = Json.fields(JSON, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("404"));
+        assert_eq!(fields.bytes, Some(91));
+        assert_eq!(&JSON[fields.url.unwrap()], "/links.json");
+        assert_eq!(fields.request_time, Some(0.004));
+        assert_eq!(
+            Json.fields(r#"{"request": "GET / HTTP/1.1"}"#, &JsonKeys::default())
+                .statuscode,
+            None
+        );
+
+        assert_eq!(
+            ErrorLog.fields(ERROR, &JsonKeys::default()).statuscode,
+            Some("crit")
+        );
+        // a line that doesn't fit the format
+        assert_eq!(
+            Json.fields(COMBINED, &JsonKeys::default()),
+            Fields::default()
+        );
+    }
+}
@@ -0,0 +1,133 @@
+// Traefik's access logs, in its common log format or as JSON. The CLF is the
+// combined format with the number of requests, the router, the server and the
+// duration added:
+//
+//     ... "GET /api HTTP/1.1" 200 1234 "-" "curl/8" 42 "api@docker" "http://10.0.0.2:80" 3ms
+
+use std::ops::Range;
+
+use super::nginx::{quoted_fields, text_fields};
+use super::{Fields, LogFormat, range_of};
+use crate::json::{self, JsonKeys};
+use crate::locate_statuscode;
+
+/// The number of requests and the duration of a CLF line, the duration is
+/// at the end after the router and server
+fn counters(line: &str) -> Option<(&str, &str)> {
+    let status = locate_statuscode(line)?;
+    let rest = line[status.end..].strip_prefix(' ')?;
+    let (_bytes, rest) = rest.split_once(' ')?;
+    let (requests, rest) = quoted_fields(rest, 2)?.split_once(' ')?;
+    requests.parse::<u64>().ok()?;
+    Some((requests, quoted_fields(rest, 2)?))
+}
+
+fn duration_ms(line: &str) -> Option<u64> {
+    counters(line)?.1.strip_suffix("ms")?.parse().ok()
+}
+
+/// Keys of JSON lines that differ between otherwise equal requests. The
+/// ClientAddr has the port, the ClientHost is the same without it.
+const VOLATILE_KEYS: [&str; 9] = [
+    "ClientAddr",
+    "ClientPort",
+    "Duration",
+    "OriginDuration",
+    "Overhead",
+    "RequestCount",
+    "StartLocal",
+    "StartUTC",
+    "time",
+];
+
+pub struct Clf;
+impl LogFormat for Clf {
+    fn name(&self) -> &'static str {
+        "traefik_clf"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        duration_ms(line).is_some()
+    }
+
+    fn fields<'a>(&self, line: &'a str, _: &JsonKeys) -> Fields<'a> {
+        Fields {
+            request_time: duration_ms(line).map(|x| x as f32 / 1000.0),
+            ..text_fields(line)
+        }
+    }
+
+    fn volatile(&self, line: &str) -> Option<Vec<Range<usize>>> {
+        let (requests, duration) = counters(line)?;
+        let mut volatile = vec![range_of(line, requests), range_of(line, duration)];
+        if let (Some(start), Some(end)) = (line.find('['), line.find(']')) {
+            volatile.push(start + 1..end);
+        }
+        Some(volatile)
+    }
+}
+
+pub struct Json;
+impl LogFormat for Json {
+    fn name(&self) -> &'static str {
+        "traefik_json"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        json::member(line, "DownstreamStatus").is_some()
+    }
+
+    fn fields<'a>(&self, line: &'a str, _: &JsonKeys) -> Fields<'a> {
+        let number = |name| json::member(line, name).and_then(|x| line[x].parse::<u64>().ok());
+        let url = json::member(line, "RequestPath")
+            .filter(|x| line[x.clone()].starts_with('"'))
+            .map(|x| x.start + 1..x.end - 1);
+        Fields {
+            statuscode: json::member(line, "DownstreamStatus").map(|x| line[x].trim_matches('"')),
+            bytes: number("DownstreamContentSize"),
+            url,
+            // in nanoseconds
+            request_time: number("Duration").map(|x| (x as f64 / 1e9) as f32),
+        }
+    }
+
+    fn volatile(&self, line: &str) -> Option<Vec<Range<usize>>> {
+        Some(json::members_named(line, &VOLATILE_KEYS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clf, Json};
+    use crate::format::LogFormat as _;
+    use crate::format::nginx::Combined;
+    use crate::json::JsonKeys;
+
+    const CLF: &str = r#"192.168.1.7 - - [10/Oct/2023:13:55:36 +0000] "GET /api/users HTTP/1.1" 502 1234 "-" "curl/8.1" 42 "api@docker" "http://10.0.0.2:80" 87ms"#;
+    const JSON: &str = r#"{"ClientAddr":"192.168.1.7:52130","DownstreamContentSize":1234,"DownstreamStatus":404,"Duration":2589000,"OriginStatus":404,"RequestMethod":"GET","RequestPath":"/api/users","RouterName":"api@docker","StartUTC":"2023-10-10T13:55:36.1Z"}"#;
+
+    #[test]
+    fn test_clf() {
+        assert!(Clf.matches(CLF));
+        assert!(!Combined.matches(CLF));
+        let fields = Clf.fields(CLF, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("502"));
+        assert_eq!(fields.bytes, Some(1234));
+        assert_eq!(&CLF[fields.url.unwrap()], "/api/users");
+        assert_eq!(fields.request_time, Some(0.087));
+
+        let combined = &CLF[..CLF.find(" 42 ").unwrap()];
+        assert!(!Clf.matches(combined));
+    }
+
+    #[test]
+    fn test_json() {
+        assert!(Json.matches(JSON));
+        assert!(!Json.matches(r#"{"status": 200}"#));
+        let fields = Json.fields(JSON, &JsonKeys::default());
+        assert_eq!(fields.statuscode, Some("404"));
+        assert_eq!(fields.bytes, Some(1234));
+        assert_eq!(&JSON[fields.url.unwrap()], "/api/users");
+        assert_eq!(fields.request_time, Some(0.002589));
+    }
+}
@@ -0,0 +1,401 @@
+// Access logs written with `log_format ... escape=json '{...}'`: one JSON
+// object per line. The members are found in a single pass over the line and
+// are slices of it, nothing gets built or copied until a value is needed.
+
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::ops::Range;
+
+use crate::parsing::code2color;
+use crate::terminal::colors;
+
+/// The fields we need from a log line, named after the nginx variables
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Status,
+    Request,     // "GET /some/url HTTP/1.1"
+    RequestUri,  // only used when there's no request
+    RequestTime, // seconds
+    BodyBytesSent,
+}
+impl Field {
+    const ALL: [Field; 5] = [
+        Field::Status,
+        Field::Request,
+        Field::RequestUri,
+        Field::RequestTime,
+        Field::BodyBytesSent,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Field::Status => "status",
+            Field::Request => "request",
+            Field::RequestUri => "request_uri",
+            Field::RequestTime => "request_time",
+            Field::BodyBytesSent => "body_bytes_sent",
+        }
+    }
+}
+
+/// Which JSON keys hold which Field, picked at startup with --json-key
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonKeys {
+    keys: Vec<(Field, String)>,
+    customised: Vec<Field>, // these no longer have their default keys
+}
+impl Default for JsonKeys {
+    fn default() -> Self {
+        let mut keys: Vec<(Field, String)> = Field::ALL
+            .iter()
+            .map(|x| (*x, x.name().to_owned()))
+            .collect();
+        keys.push((Field::RequestUri, "uri".to_owned()));
+        Self {
+            keys,
+            customised: vec![],
+        }
+    }
+}
+impl JsonKeys {
+    /// Parses FIELD=KEY, the first key given for a field replaces its
+    /// defaults, the ones after that are added
+    pub fn add(&mut self, mapping: &str) -> Result<(), String> {
+        let Some((name, key)) = mapping.split_once('=') else {
+            return Err(format!("Expected FIELD=KEY, got {mapping:?}"));
+        };
+        let Some(field) = Field::ALL.into_iter().find(|x| x.name() == name) else {
+            let names: Vec<&str> = Field::ALL.iter().map(|x| x.name()).collect();
+            return Err(format!(
+                "Unknown field {name:?}, expected one of {}",
+                names.join(", ")
+            ));
+        };
+        if key.is_empty() {
+            return Err(format!("Empty key for {name}"));
+        }
+        if !self.customised.contains(&field) {
+            self.customised.push(field);
+            self.keys.retain(|(x, _)| *x != field);
+        }
+        self.keys.push((field, key.to_owned()));
+        Ok(())
+    }
+
+    pub fn field(&self, key: &str) -> Option<Field> {
+        self.keys.iter().find(|(_, x)| x == key).map(|(x, _)| *x)
+    }
+}
+
+pub fn is_json(line: &str) -> bool {
+    line.trim_start().starts_with('{')
+}
+
+/// A "key": value pair of the top level object, as byte ranges of the line.
+/// Both ranges include the quotes of strings.
+#[derive(Debug, PartialEq)]
+pub struct Member {
+    pub key: Range<usize>,
+    pub value: Range<usize>,
+}
+
+/// The members of the object on a line, in order. Nested objects and arrays
+/// are a single value. Stops at the end of the object or at the first thing
+/// that isn't JSON, `end` tells how far it got.
+pub struct Members<'a> {
+    line: &'a [u8],
+    pub end: usize, // everything before this has been parsed
+}
+
+impl<'a> Members<'a> {
+    pub fn new(line: &'a str) -> Option<Self> {
+        let start = line.find(|x: char| !x.is_ascii_whitespace())?;
+        match line[start..].starts_with('{') {
+            true => Some(Self {
+                line: line.as_bytes(),
+                end: start + 1,
+            }),
+            false => None,
+        }
+    }
+
+    fn skip_whitespace(&self, mut position: usize) -> usize {
+        while self.line.get(position).is_some_and(u8::is_ascii_whitespace) {
+            position += 1;
+        }
+        position
+    }
+
+    /// The position after the string that starts at `position`
+    fn skip_string(&self, mut position: usize) -> Option<usize> {
+        position += 1; // the opening quote
+        loop {
+            match self.line.get(position)? {
+                b'\\' => position += 2,
+                b'"' => return Some(position + 1),
+                _ => position += 1,
+            }
+        }
+    }
+
+    /// The position after the value that starts at `position`
+    fn skip_value(&self, mut position: usize) -> Option<usize> {
+        match self.line.get(position)? {
+            b'"' => self.skip_string(position),
+            b'{' | b'[' => {
+                let mut depth = 0;
+                loop {
+                    match self.line.get(position)? {
+                        b'"' => {
+                            position = self.skip_string(position)?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => depth -= 1,
+                        _ => {}
+                    }
+                    position += 1;
+                    if depth == 0 {
+                        return Some(position);
+                    }
+                }
+            }
+            _ => {
+                // numbers, true, false and null
+                let start = position;
+                while self
+                    .line
+                    .get(position)
+                    .is_some_and(|x| !matches!(x, b',' | b'}' | b']') && !x.is_ascii_whitespace())
+                {
+                    position += 1;
+                }
+                (position > start).then_some(position)
+            }
+        }
+    }
+
+    fn parse_member(&self) -> Option<Member> {
+        let mut position = self.skip_whitespace(self.end);
+        if self.line.get(position) == Some(&b',') {
+            position = self.skip_whitespace(position + 1);
+        }
+        if self.line.get(position) != Some(&b'"') {
+            return None;
+        }
+        let key = position..self.skip_string(position)?;
+        position = self.skip_whitespace(key.end);
+        if self.line.get(position) != Some(&b':') {
+            return None;
+        }
+        position = self.skip_whitespace(position + 1);
+        let value = position..self.skip_value(position)?;
+        Some(Member { key, value })
+    }
+}
+
+impl Iterator for Members<'_> {
+    type Item = Member;
+
+    fn next(&mut self) -> Option<Member> {
+        let member = self.parse_member()?;
+        self.end = member.value.end;
+        Some(member)
+    }
+}
+
+/// A string value without its quotes and escapes, anything else as is
+pub fn unquote(raw: &str) -> Cow<'_, str> {
+    let Some(inner) = raw.strip_prefix('"').and_then(|x| x.strip_suffix('"')) else {
+        return Cow::Borrowed(raw);
+    };
+    if !inner.contains('\\') {
+        return Cow::Borrowed(inner);
+    }
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            text.push(chr);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('r') => text.push('\r'),
+            Some('t') => text.push('\t'),
+            Some('b') => text.push('\u{8}'),
+            Some('f') => text.push('\u{c}'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let code = u32::from_str_radix(&hex, 16).ok();
+                // surrogate pairs are rare enough in log lines to not bother
+                text.push(code.and_then(char::from_u32).unwrap_or('\u{fffd}'));
+            }
+            Some(chr) => text.push(chr), // \" \\ \/
+            None => {}
+        }
+    }
+    Cow::Owned(text)
+}
+
+/// The key of the Member without its quotes
+pub fn key<'a>(line: &'a str, member: &Member) -> &'a str {
+    &line[member.key.start + 1..member.key.end - 1]
+}
+
+/// The raw values of the fields, from the first member holding each
+#[derive(Debug, Default, PartialEq)]
+pub struct Values<'a> {
+    pub status: Option<&'a str>,
+    pub request: Option<&'a str>,
+    pub request_uri: Option<&'a str>,
+    pub request_time: Option<&'a str>,
+    pub body_bytes_sent: Option<&'a str>,
+}
+
+impl<'a> Values<'a> {
+    /// All fields in a single pass over the members
+    pub fn new(line: &'a str, keys: &JsonKeys) -> Self {
+        let mut values = Self::default();
+        for member in Members::new(line).into_iter().flatten() {
+            let value = match keys.field(key(line, &member)) {
+                Some(Field::Status) => &mut values.status,
+                Some(Field::Request) => &mut values.request,
+                Some(Field::RequestUri) => &mut values.request_uri,
+                Some(Field::RequestTime) => &mut values.request_time,
+                Some(Field::BodyBytesSent) => &mut values.body_bytes_sent,
+                None => continue,
+            };
+            value.get_or_insert(&line[member.value]);
+        }
+        values
+    }
+
+    /// The URL from request_uri or else from the request
+    pub fn url(&self) -> Option<&'a str> {
+        if let Some(uri) = self.request_uri {
+            // escape=json doesn't escape slashes, so the raw value will do
+            return Some(uri.trim_matches('"'));
+        }
+        self.request?.trim_matches('"').split(' ').nth(1)
+    }
+}
+
+/// The raw value of the member named `name`, for logs with fixed keys
+pub fn member(line: &str, name: &str) -> Option<Range<usize>> {
+    Members::new(line)?
+        .find(|x| key(line, x) == name)
+        .map(|x| x.value)
+}
+
+/// The raw values of the members named in `names`, in order
+pub fn members_named(line: &str, names: &[&str]) -> Vec<Range<usize>> {
+    Members::new(line)
+        .into_iter()
+        .flatten()
+        .filter(|x| names.contains(&key(line, x)))
+        .map(|x| x.value)
+        .collect()
+}
+
+/// Highlights a JSON line: keys are dimmed, the status gets its color and
+/// POST requests stand out
+pub struct JsonLine<'a>(pub &'a str, pub &'a JsonKeys);
+
+impl Display for JsonLine<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let JsonLine(line, keys) = *self;
+        let Some(mut members) = Members::new(line) else {
+            return write!(f, "{line}");
+        };
+        let (dim, reset) = (colors::theme().dim, colors::RESET);
+        let mut written = 0;
+        for member in members.by_ref() {
+            let value = &line[member.value.clone()];
+            let (color, color_reset) = match keys.field(key(line, &member)) {
+                Some(Field::Status) => code2color(&unquote(value)),
+                Some(Field::Request) if value.starts_with("\"POST ") => {
+                    (colors::theme().highlight, colors::RESET)
+                }
+                _ => ("", ""),
+            };
+            write!(
+                f,
+                "{}{dim}{}{reset}{}{color}{value}{color_reset}",
+                &line[written..member.key.start],
+                &line[member.key.clone()],
+                &line[member.key.end..member.value.start],
+            )?;
+            written = member.value.end;
+        }
+        write!(f, "{}", &line[written..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Field, JsonKeys, JsonLine, Members, Values, key, unquote};
+    use crate::terminal::colors::{GREEN, GREY, RESET};
+
+    const LINE: &str = r#"{"time_local": "26/May/2025:00:00:01 +0200", "request": "GET /users/12?a=\"b\" HTTP/1.1", "status": 200, "headers": {"x": [1, "}"]}, "request_time":"0.023"}"#;
+
+    #[test]
+    fn test_members() {
+        let keys: Vec<&str> = Members::new(LINE).unwrap().map(|x| key(LINE, &x)).collect();
+        assert_eq!(
+            keys,
+            vec!["time_local", "request", "status", "headers", "request_time"]
+        );
+        let values = Values::new(LINE, &JsonKeys::default());
+        assert_eq!(values.status, Some("200"));
+        assert_eq!(values.request_time, Some("\"0.023\""));
+        assert_eq!(values.body_bytes_sent, None);
+        assert_eq!(values.url(), Some("/users/12?a=\\\"b\\\""));
+        let uri = r#"{"request": "GET /a HTTP/1.1", "request_uri": "/b", "request_uri": "/c"}"#;
+        assert_eq!(Values::new(uri, &JsonKeys::default()).url(), Some("/b"));
+
+        // a cut off line gives what's there
+        let cut = &LINE[..LINE.find("\"headers").unwrap() + 12];
+        let mut members = Members::new(cut).unwrap();
+        assert_eq!(members.by_ref().count(), 3);
+        assert_eq!(&cut[members.end..], ", \"headers\": {");
+
+        assert!(Members::new("1.2.3.4 - - [date]").is_none());
+    }
+
+    #[test]
+    fn test_unquote() {
+        assert_eq!(unquote("200"), "200");
+        assert_eq!(unquote("\"plain\""), "plain");
+        assert_eq!(unquote(r#""a\"b\\c\/dé\n""#), "a\"b\\c/dé\n");
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut keys = JsonKeys::default();
+        assert_eq!(keys.field("uri"), Some(Field::RequestUri));
+        keys.add("status=http_status").unwrap();
+        keys.add("status=upstream_status").unwrap();
+        assert_eq!(keys.field("status"), None);
+        assert_eq!(keys.field("http_status"), Some(Field::Status));
+        assert_eq!(keys.field("upstream_status"), Some(Field::Status));
+        assert!(keys.add("status").is_err());
+        assert!(keys.add("teapot=x").is_err());
+        assert!(keys.add("status=").is_err());
+
+        let line = r#"{"status": 0, "upstream_status": 502}"#;
+        assert_eq!(Values::new(line, &keys).status, Some("502"));
+        assert_eq!(Values::new(line, &JsonKeys::default()).status, Some("0"));
+    }
+
+    #[test]
+    fn test_highlighting() {
+        let line = r#"{"status": "200", "x": 1 } "#;
+        let keys = JsonKeys::default();
+        assert_eq!(
+            JsonLine(line, &keys).to_string(),
+            format!(r#"{{{GREY}"status"{RESET}: {GREEN}"200"{RESET}, {GREY}"x"{RESET}: 1 }} "#)
+        );
+        assert_eq!(JsonLine("not json", &keys).to_string(), "not json");
+    }
+}
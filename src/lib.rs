@@ -1,13 +1,27 @@
+pub mod backlog;
 mod collections;
-mod parsing;
+mod dedup;
+pub mod diagnostics;
+pub mod filter;
+pub mod format;
+pub mod json;
+pub mod parsing;
+mod pattern;
+mod render;
+pub mod routes;
+pub mod sampling;
+mod screen;
 mod speedometer;
 pub mod terminal;
 
 use std::cmp;
 use std::collections::VecDeque;
+use std::io::SeekFrom;
 use std::io::Write as _;
+use std::ops::Range;
 use std::os::fd::AsRawFd as _;
 use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 use std::vec;
 use std::{fmt::Display, path::PathBuf, time::Duration};
 
@@ -24,10 +38,20 @@ use smol::{
     channel::{Receiver, Sender},
 };
 
-use crate::collections::GroupMap;
-use crate::parsing::code2color;
-use crate::parsing::parse_nginx_line;
+use crate::backlog::Backlog;
+use crate::collections::{FileInfo, FocusStats, GroupMap, Interner};
+use crate::dedup::{Repeated, Repeats};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::filter::Filter;
+use crate::format::{Detection, Fields, Format, first_lines};
+use crate::json::JsonKeys;
+use crate::parsing::highlight;
+use crate::render::{ShownLine, StatsRow, Status};
+use crate::routes::RouteNormaliser;
+use crate::sampling::{Sampler, Strategy};
+use crate::screen::Screen;
 use crate::speedometer::{RingbufferSpeedometer, Speedometer};
+use crate::terminal::Key;
 use crate::terminal::colors;
 use crate::terminal::colors::CSI;
 
@@ -38,33 +62,55 @@ pub fn get_statuscode_class(statuscode: &str) -> Option<String> {
     // 3xx (Redirection): Further action needs to be taken in order to complete the request
     // 4xx (Client Error): The request contains bad syntax or cannot be fulfilled
     // 5xx (Server Error): The server failed to fulfill an apparently valid request
-    statuscode.chars().next().map(|x| format!("{x}xx"))
+    // Anything else, fe. the severity of an error log line, has no class.
+    statuscode
+        .chars()
+        .next()
+        .filter(char::is_ascii_digit)
+        .map(|x| format!("{x}xx"))
 }
 
-fn extract_statuscode(line: &str) -> Result<String, String> {
-    if let Some(first_quote) = line.find('"') {
-        if line.len() < first_quote + 1 {
-            return Err("?D".to_owned());
+/// Byte range of $status: the first three digit field after the quoted request
+fn locate_statuscode(line: &str) -> Option<Range<usize>> {
+    let first_quote = line.find('"')?;
+    let mut start = first_quote + 1 + line[first_quote + 1..].find('"')? + 1;
+    for field in line[start..].split(' ') {
+        if field.len() == 3 && field.bytes().all(|x| x.is_ascii_digit()) {
+            return Some(start..start + 3);
         }
-        if let Some(second_quote) = line[first_quote + 1..].find('"') {
-            if line.len() < first_quote + 1 + second_quote + 2 {
-                return Err("?E".to_owned());
-            }
-            if let Some(end_space) = line[first_quote + 1 + second_quote + 2..].find(' ') {
-                Ok(line[first_quote + 1 + second_quote + 2
-                    ..first_quote + 1 + second_quote + 2 + end_space]
-                    .to_owned())
-            } else {
-                Err("?C".to_owned())
-            }
-        } else {
-            Err("?B".to_owned())
+        start += field.len() + 1;
+    }
+    None
+}
+
+/// The URL from the quoted request: "GET /some/url HTTP/1.1"
+fn extract_url(line: &str) -> Option<&str> {
+    let first_quote = line.find('"')?;
+    let request = &line[first_quote + 1..];
+    let request = &request[..request.find('"').unwrap_or(request.len())];
+    request.split(' ').nth(1)
+}
+
+/// Seconds with millisecond resolution: $request_time, $upstream_response_time
+fn is_timing(field: &[u8]) -> bool {
+    match field.len().checked_sub(4) {
+        Some(dot) => {
+            dot > 0
+                && field[dot] == b'.'
+                && field[..dot].iter().all(u8::is_ascii_digit)
+                && field[dot + 1..].iter().all(u8::is_ascii_digit)
         }
-    } else {
-        Err("?A".to_owned())
+        None => false,
     }
 }
 
+/// What the rows of the stats are made of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    File,
+    Route,
+}
+
 pub struct Error(pub String);
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
@@ -90,39 +136,63 @@ impl Display for Error {
 
 pub type SenderChannel = Sender<Message>;
 
+/// Bytes read at once, a busy log has many lines in it that go out as one batch
+const READ_SIZE: usize = 64 * 1024;
+
 struct LineReader {
     filename: PathBuf,
     fd_path: PathBuf,
+    opened: PathBuf,  // where fd_path pointed to when opened, without symlinks or ./
     file: File,       // the file handle
     pending: Vec<u8>, // data that was read but not yet processed
     readbuf: Vec<u8>,
+    state: FileState,
 }
 
 impl LineReader {
     async fn new(filename: PathBuf) -> Result<Self, String> {
-        let (file, fd_path) = Self::_open_file(filename.clone()).await?;
+        // what's in the file already isn't live anymore
+        let (file, fd_path, opened) = Self::_open_file(filename.clone(), SeekFrom::End(0)).await?;
         Ok(LineReader {
             filename,
             fd_path,
+            opened,
             file,
             pending: vec![],
-            readbuf: vec![0; 1024],
+            readbuf: vec![0; READ_SIZE],
+            state: FileState::Ok,
         })
     }
 
-    async fn _open_file(filename: PathBuf) -> Result<(File, PathBuf), String> {
+    /// Opens the file with this name again, a rotated one is read from the
+    /// start as nginx writes to it before we notice the rotation
+    async fn reopen(&mut self, from: SeekFrom) -> Result<(), String> {
+        let (file, fd_path, opened) = Self::_open_file(self.filename.clone(), from).await?;
+        self.file = file;
+        self.fd_path = fd_path;
+        self.opened = opened;
+        self.pending.clear();
+        Ok(())
+    }
+
+    async fn _open_file(
+        filename: PathBuf,
+        from: SeekFrom,
+    ) -> Result<(File, PathBuf, PathBuf), String> {
         // open the file and get the /proc/self/fd/<fd> path
         let mut file = smol::fs::File::open(&filename)
             .await
             .map_err(|e| e.to_string())?;
-        if file.seek(std::io::SeekFrom::End(0)).await.is_err() {
-            return Err("Error seeking to end of file".into());
+        if file.seek(from).await.is_err() {
+            return Err("Error seeking in file".into());
         }
         let fd_path = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
-        Ok((file, fd_path))
+        // the link is absolute, the filename we were given might not be
+        let opened = read_link(&fd_path).await.map_err(|e| e.to_string())?;
+        Ok((file, fd_path, opened))
     }
 
-    async fn read_lines(&mut self) -> Result<Vec<String>, ()> {
+    async fn read_lines(&mut self) -> Result<Vec<String>, String> {
         match self.file.read(&mut self.readbuf).await {
             Ok(0) => {
                 // Did the file get rotated perhaps?
@@ -131,12 +201,15 @@ impl LineReader {
                 let current_filename = read_link(self.fd_path.clone())
                     .await
                     .unwrap_or_else(|_| PathBuf::new());
-                if current_filename != self.filename {
+                if current_filename != self.opened {
                     // yes, it did! Let's try to open the new file
-                    if let Ok((file, fd_path)) = Self::_open_file(self.filename.clone()).await {
-                        self.file = file;
-                        self.fd_path = fd_path;
-                        self.pending.clear();
+                    match self.reopen(SeekFrom::Start(0)).await {
+                        Ok(_) => self.state = FileState::Rotated,
+                        Err(_) => {
+                            // not (re)created yet, we'll keep reading the old one
+                            self.state = FileState::Missing;
+                            Timer::after(Duration::from_millis(50)).await;
+                        }
                     }
                 } else {
                     // no, the file is still the same. Let's wait a bit before trying again
@@ -174,60 +247,194 @@ impl LineReader {
                 self.pending.drain(..start_of_next);
                 Ok(whole_lines)
             }
-            Err(_) => Err(()),
+            Err(e) => Err(e.to_string()),
         }
     }
 }
 
+/// What follow knows about the file it's reading
+#[derive(Debug, PartialEq, Clone)]
+pub enum FileState {
+    Ok,
+    Rotated, // reported once when a new file with the same name was opened
+    Missing, // deleted or renamed, we're still reading the old one
+    Unreadable(String),
+}
+
+impl FileState {
+    /// What to tell the user when a file ends up in this state
+    pub fn diagnostic(&self, file: &str) -> Option<Diagnostic> {
+        match self {
+            FileState::Ok => None,
+            FileState::Rotated => Some(Diagnostic::new(
+                Severity::Info,
+                format!("{file} was rotated, reading the new file"),
+            )),
+            FileState::Missing => Some(Diagnostic::new(
+                Severity::Warning,
+                format!("{file} is missing, still reading the old file"),
+            )),
+            FileState::Unreadable(reason) => Some(Diagnostic::new(
+                Severity::Error,
+                format!("{file} is not readable: {reason}"),
+            )),
+        }
+    }
+}
+
+/// Sends the state and mtime of the file, but only when they changed since last time
+async fn report_file_state(
+    channel: &SenderChannel,
+    file: &str,
+    state: FileState,
+    mtime: Option<SystemTime>,
+    last_reported: &mut Option<(FileState, Option<SystemTime>)>,
+) -> Result<(), SendError<Message>> {
+    let current = Some((state.clone(), mtime));
+    if *last_reported == current {
+        return Ok(());
+    }
+    *last_reported = current;
+    channel
+        .send(Message::FileStatus {
+            file: file.to_owned(),
+            state,
+            mtime,
+        })
+        .await
+}
+
+async fn report_format(
+    channel: &SenderChannel,
+    file: &str,
+    format: Format,
+) -> Result<(), SendError<Message>> {
+    let file = file.to_owned();
+    channel.send(Message::FileFormat { file, format }).await
+}
+
+/// Tails the file, with the format given by the user or else detected from
+/// its first lines
 pub async fn follow(
     channel: SenderChannel,
+    backlog: Backlog,
     file: PathBuf,
-    updowngroup: String,
-    leftrightextractor: fn(&str) -> Option<String>,
+    format: Option<Format>,
+    keys: JsonKeys,
 ) {
+    let updowngroup = file.display().to_string();
     channel
         .send(Message::RegisterGroup(updowngroup.clone()))
         .await
         .unwrap();
-    let mut processor = match LineReader::new(file).await {
-        Ok(x) => x,
-        Err(e) => {
-            eprintln!("Error opening file: {e}");
-            return;
+    // shared by every line instead of a copy each
+    let group: Arc<str> = updowngroup.as_str().into();
+    let mut statuscodes = Interner::default();
+    let mut last_reported = None;
+    let mut processor = loop {
+        match LineReader::new(file.clone()).await {
+            Ok(x) => break x,
+            Err(e) => {
+                let state = FileState::Unreadable(e);
+                let report =
+                    report_file_state(&channel, &updowngroup, state, None, &mut last_reported);
+                if report.await.is_err() {
+                    // Channel closed
+                    return;
+                }
+                Timer::after(Duration::from_secs(1)).await;
+            }
         }
     };
+    let mut detection = Detection::new(format, keys);
+    detection.sample(&first_lines(&file).await);
+    if let Some(format) = detection.format
+        && report_format(&channel, &updowngroup, format).await.is_err()
+    {
+        // Channel closed
+        return;
+    }
+    let mut last_check = Instant::now();
     loop {
         match processor.read_lines().await {
             Ok(lines) => {
-                for line in lines {
-                    let statuscode = extract_statuscode(&line).ok();
-                    let leftrightgroup = match statuscode.as_deref() {
-                        None => None,
-                        Some(x) => leftrightextractor(x),
-                    };
-                    if channel
-                        .send(Message::Line {
-                            text: line,
-                            updowngroup: updowngroup.clone(),
-                            leftrightgroup,
-                            statuscode,
+                // an empty file gets its format from the first lines written to it
+                if let Some(format) = detection.sample(&lines)
+                    && report_format(&channel, &updowngroup, format).await.is_err()
+                {
+                    // Channel closed
+                    return;
+                }
+                // nothing new, or only part of a line
+                if !lines.is_empty() {
+                    let lines = lines
+                        .into_iter()
+                        .map(|text| {
+                            let Fields {
+                                statuscode,
+                                bytes,
+                                url,
+                                request_time,
+                            } = detection.fields(&text);
+                            Line {
+                                statuscode: statuscode.map(|x| statuscodes.get(x)),
+                                text,
+                                bytes,
+                                url,
+                                request_time,
+                                dropped: false,
+                            }
                         })
-                        .await
-                        .is_err()
-                    {
+                        .collect();
+                    if backlog.send(&channel, group.clone(), lines).await.is_err() {
                         // Channel closed
                         return;
                     }
                 }
             }
-            Err(_) => {
-                eprintln!("File is no longer readable");
+            Err(e) => {
+                processor.state = FileState::Unreadable(e);
+                // try again in a bit, hopefully someone fixed the permissions
+                Timer::after(Duration::from_secs(1)).await;
+                // we can't tell where we were, so only lines from now on
+                if processor.reopen(SeekFrom::End(0)).await.is_ok() {
+                    processor.state = FileState::Ok;
+                }
+            }
+        }
+
+        // unlike reading this needs a syscall, so we don't do it all the time
+        if last_check.elapsed() >= Duration::from_secs(1) || processor.state != FileState::Ok {
+            last_check = Instant::now();
+            let mtime = match smol::fs::metadata(&file).await {
+                Ok(metadata) => metadata.modified().ok(),
+                Err(_) => None,
+            };
+            let state = processor.state.clone();
+            let report =
+                report_file_state(&channel, &updowngroup, state, mtime, &mut last_reported);
+            if report.await.is_err() {
+                // Channel closed
                 return;
             }
+            if processor.state == FileState::Rotated {
+                processor.state = FileState::Ok;
+            }
         }
     }
 }
 
+/// A log line with the fields its format knows about
+#[derive(Debug, PartialEq)]
+pub struct Line {
+    pub text: String,
+    pub statuscode: Option<Arc<str>>, // 200, 403, 404; bucketing into 2xx happens when showing stats
+    pub bytes: Option<u64>,           // $body_bytes_sent
+    pub url: Option<Range<usize>>,    // byte range of the text
+    pub request_time: Option<f32>,    // seconds
+    pub dropped: bool,                // only the url is left of the text, see backlog
+}
+
 /// Message to be sent to the processing thread
 #[derive(Debug, PartialEq)]
 pub enum Message {
@@ -235,16 +442,80 @@ pub enum Message {
         include_lines: bool,
     },
     RegisterGroup(String), // optional; can be used when you know upfront what the tags are
-    Line {
-        text: String,
-        updowngroup: String, // usually "/var/log/nginx/site1/access.log", but can be "fe. "Total"
-        leftrightgroup: Option<String>, // either 200,403,404 or 2xx,4xx
-        statuscode: Option<String>, // 200, 403, 404
+    Lines {
+        updowngroup: Arc<str>, // usually "/var/log/nginx/site1/access.log", but can be fe. "generator"
+        lines: Vec<Line>,      // everything read at once, oldest first
     },
-    WinCh(u16),
+    WinCh(u16, u16),        // width, height
+    Prompt(Option<String>), // one-line prompt at the bottom of the TUI, None hides it
+    Filter(Filter),         // replaces the active filter
+    Toggle(Toggle),
+    Navigate(Navigation),
+    FileStatus {
+        file: String, // as used in RegisterGroup
+        state: FileState,
+        mtime: Option<SystemTime>,
+    },
+    FileFormat {
+        file: String, // as used in RegisterGroup
+        format: Format,
+    },
+    Diagnostic(Diagnostic),
+}
+
+/// Moving around the groups in the stats and through long lines
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Navigation {
+    Up,
+    Down,
+    Left,  // scroll the lines back to the start
+    Right, // scroll the lines towards the end
+    Enter, // focus on the selected group
+    Back,  // close the help, leave the focus or drop the selection
 }
 
-pub async fn keyboard_reader(channel: SenderChannel) -> Result<(), Error> {
+/// Views that can be switched on and off while running
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Toggle {
+    Combine, // all groups on a single row
+    Merge,   // status codes bucketed into 2xx, 3xx, ...
+    Help,    // overlay listing keys, filters and inputs
+    Pause,   // freeze the lines, the stats keep running
+    Log,     // the warnings and errors so far instead of the stats
+    Bars,    // the share of every status class as bars instead of numbers
+    Heatmap, // latencies of the selected group over time instead of the stats
+    Dedup,   // repeated lines once with a count
+}
+
+/// The current state of every Toggle
+#[derive(Debug, Default, Clone)]
+pub struct Modes {
+    pub combine: bool,
+    pub merge: bool,
+    pub help: bool,
+    pub paused: bool,
+    pub log: bool,
+    pub bars: bool,
+    pub heatmap: bool,
+    pub dedup: bool,
+}
+impl Modes {
+    pub fn toggle(&mut self, toggle: Toggle) {
+        let mode = match toggle {
+            Toggle::Combine => &mut self.combine,
+            Toggle::Merge => &mut self.merge,
+            Toggle::Help => &mut self.help,
+            Toggle::Pause => &mut self.paused,
+            Toggle::Log => &mut self.log,
+            Toggle::Bars => &mut self.bars,
+            Toggle::Heatmap => &mut self.heatmap,
+            Toggle::Dedup => &mut self.dedup,
+        };
+        *mode = !*mode;
+    }
+}
+
+pub async fn keyboard_reader(channel: SenderChannel, filter: Filter) -> Result<(), Error> {
     // NB: We're using tty and not stdin: we want to allow users to pipe
     // data into the program via stdin still
 
@@ -254,37 +525,119 @@ pub async fn keyboard_reader(channel: SenderChannel) -> Result<(), Error> {
         .await
         .map_err(|e| Error(format!("Failed to open /dev/tty: {e:?}")))?;
 
-    eprintln!("[q] to quit, [l] to flush lines, [s] to update stats");
+    // The filter as the user typed it last time, used to prefill the prompt
+    let mut filter_text = filter.to_string();
+    // Some(text) while the user is editing the filter
+    let mut editing: Option<String> = None;
+    let mut reported_no_input = false;
+
     loop {
-        let mut buffer = [0; 1];
+        let mut buffer = [0; 32];
         match tty.read(&mut buffer).await {
             Ok(0) => {
                 // No input, just yield to avoid busy loop
-                eprintln!("No input, waiting...");
+                if !reported_no_input {
+                    reported_no_input = true;
+                    let diagnostic = Diagnostic::new(Severity::Info, "No input, waiting...");
+                    channel.send(Message::Diagnostic(diagnostic)).await?;
+                }
                 std::thread::sleep(std::time::Duration::from_millis(10));
                 continue;
             }
-            Ok(_) => {
-                let c = buffer[0] as char;
-                match c {
-                    'q' | 'Q' => {
-                        raise(SIGINT).unwrap(); // to trigger terminal restoration, see sigint_handler
-                    }
-                    's' | 'S' => {
+            Ok(n) => {
+                for key in terminal::parse_keys(&buffer[..n]) {
+                    if let Some(text) = editing.as_mut() {
+                        match key {
+                            Key::Char(x) => text.push(x),
+                            Key::Backspace => {
+                                text.pop();
+                            }
+                            Key::Escape => {
+                                editing = None;
+                                channel.send(Message::Prompt(None)).await?;
+                                continue;
+                            }
+                            Key::Enter => match Filter::parse(text) {
+                                Ok(filter) => {
+                                    filter_text = filter.to_string();
+                                    editing = None;
+                                    channel.send(Message::Filter(filter)).await?;
+                                    channel.send(Message::Prompt(None)).await?;
+                                    continue;
+                                }
+                                Err(e) => {
+                                    channel
+                                        .send(Message::Prompt(Some(format!(
+                                            "filter> {text}   ({e})"
+                                        ))))
+                                        .await?;
+                                    continue;
+                                }
+                            },
+                            _ => {}
+                        }
                         channel
-                            .send(Message::Print {
-                                include_lines: false,
-                            })
+                            .send(Message::Prompt(Some(format!("filter> {text}"))))
                             .await?;
+                        continue;
                     }
-                    'l' | 'L' => {
-                        channel
-                            .send(Message::Print {
-                                include_lines: true,
-                            })
-                            .await?;
+
+                    match key {
+                        Key::Char('q' | 'Q') => {
+                            raise(SIGINT).unwrap(); // to trigger terminal restoration, see sigint_handler
+                        }
+                        Key::Char('s' | 'S') => {
+                            channel
+                                .send(Message::Print {
+                                    include_lines: false,
+                                })
+                                .await?;
+                        }
+                        Key::Char('l' | 'L') => {
+                            channel
+                                .send(Message::Print {
+                                    include_lines: true,
+                                })
+                                .await?;
+                        }
+                        Key::Char('c' | 'C') => {
+                            channel.send(Message::Toggle(Toggle::Combine)).await?;
+                        }
+                        Key::Char('m' | 'M') => {
+                            channel.send(Message::Toggle(Toggle::Merge)).await?;
+                        }
+                        Key::Char(' ') => {
+                            channel.send(Message::Toggle(Toggle::Pause)).await?;
+                        }
+                        Key::Char('?') => {
+                            channel.send(Message::Toggle(Toggle::Help)).await?;
+                        }
+                        Key::Char('b' | 'B') => {
+                            channel.send(Message::Toggle(Toggle::Bars)).await?;
+                        }
+                        Key::Char('h' | 'H') => {
+                            channel.send(Message::Toggle(Toggle::Heatmap)).await?;
+                        }
+                        Key::Char('u' | 'U') => {
+                            channel.send(Message::Toggle(Toggle::Dedup)).await?;
+                        }
+                        Key::Char('d' | 'D') => {
+                            channel.send(Message::Toggle(Toggle::Log)).await?;
+                        }
+                        Key::Up => channel.send(Message::Navigate(Navigation::Up)).await?,
+                        Key::Down => channel.send(Message::Navigate(Navigation::Down)).await?,
+                        Key::Left => channel.send(Message::Navigate(Navigation::Left)).await?,
+                        Key::Right => channel.send(Message::Navigate(Navigation::Right)).await?,
+                        Key::Enter => channel.send(Message::Navigate(Navigation::Enter)).await?,
+                        Key::Escape => channel.send(Message::Navigate(Navigation::Back)).await?,
+                        Key::Char('f' | 'F') => {
+                            channel
+                                .send(Message::Prompt(Some(format!("filter> {filter_text}"))))
+                                .await?;
+                            editing = Some(filter_text.clone());
+                        }
+                        _ => {}
                     }
-                    _ => {}
                 }
             }
             Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
@@ -293,7 +646,10 @@ pub async fn keyboard_reader(channel: SenderChannel) -> Result<(), Error> {
                 continue;
             }
             Err(e) => {
-                eprintln!("Error reading from tty: {e}");
+                let text = format!("Error reading from tty, the keys won't work: {e}");
+                channel
+                    .send(Message::Diagnostic(Diagnostic::new(Severity::Error, text)))
+                    .await?;
                 return Err(Error(e.to_string()));
             }
         }
@@ -342,19 +698,20 @@ pub async fn periodic_print(channel: SenderChannel) -> Result<(), Error> {
 }
 
 #[cfg(debug_assertions)]
-pub async fn fake_slow(channel: SenderChannel) {
+pub async fn fake_slow(channel: SenderChannel, backlog: Backlog) {
     let mut counter: u32 = 0;
+    let (group, statuscode): (Arc<str>, Arc<str>) = ("generator".into(), "slow".into());
     loop {
         Timer::after(Duration::from_secs(2)).await;
-        match channel
-            .send(Message::Line {
-                text: format!("Fake slow msg {counter}"),
-                statuscode: Some("slow".to_owned()),
-                updowngroup: "generator".to_owned(),
-                leftrightgroup: Some("200".to_owned()),
-            })
-            .await
-        {
+        let line = Line {
+            text: format!("Fake slow msg {counter}"),
+            statuscode: Some(statuscode.clone()),
+            bytes: Some(512),
+            url: None,
+            request_time: None,
+            dropped: false,
+        };
+        match backlog.send(&channel, group.clone(), vec![line]).await {
             Ok(_) => counter += 1,
             Err(_) => {
                 // Channel closed
@@ -364,275 +721,703 @@ pub async fn fake_slow(channel: SenderChannel) {
     }
 }
 #[cfg(debug_assertions)]
-pub async fn fake_fast(channel: SenderChannel) {
+pub async fn fake_fast(channel: SenderChannel, backlog: Backlog) {
     let mut j = 0;
+    let (group, statuscode): (Arc<str>, Arc<str>) = ("generator".into(), "200".into());
     loop {
         j += 1;
         Timer::after(Duration::from_millis(100)).await;
-        for i in 0..100 {
-            match channel
-                .send(Message::Line {
-                    text: format!("[{j}] Fake fast msg {i}"),
-                    statuscode: Some("200".to_owned()),
-                    updowngroup: "generator".to_owned(),
-                    leftrightgroup: Some("fake".to_owned()),
-                })
-                .await
-            {
-                Ok(_) => {}
-                Err(_) => {
-                    // Channel closed
-                    return;
-                }
-            }
+        let lines = (0..100)
+            .map(|i| Line {
+                text: format!("[{j}] Fake fast msg {i}"),
+                statuscode: Some(statuscode.clone()),
+                bytes: Some(1024 * i),
+                url: None,
+                request_time: None,
+                dropped: false,
+            })
+            .collect();
+        if backlog.send(&channel, group.clone(), lines).await.is_err() {
+            // Channel closed
+            return;
         }
     }
 }
 
-pub async fn process_as_streaming(channel: Receiver<Message>, filters: Vec<String>) {
+pub async fn process_as_streaming(
+    channel: Receiver<Message>,
+    backlog: Backlog,
+    mut filter: Filter,
+    normaliser: RouteNormaliser,
+    keys: JsonKeys,
+    color: bool, // without it the lines are passed on untouched
+    dedup: bool, // repeated lines once with a count, see Repeats
+) {
+    let print = |repeated: Repeated| {
+        let line = match color {
+            true => highlight(&repeated.text, &keys),
+            false => repeated.text,
+        };
+        match dedup {
+            true => println!("{}", dedup::with_count(&line, repeated.count)),
+            false => println!("{line}"),
+        }
+    };
+    let report_dropped = |dropped: u64| {
+        let text = format!("dropped {dropped} lines, the output couldn't keep up");
+        eprintln!("{}", Diagnostic::new(Severity::Warning, text));
+    };
+    let mut repeats = Repeats::default();
+    let mut formats: Vec<(String, Format)> = vec![]; // per file, for the dedup keys
+    let mut dropped_lines: u64 = 0; // since the last line that was shown
     loop {
-        match channel.recv().await {
+        match backlog.recv(&channel).await {
             Err(_) => {
-                eprintln!("Channel closed");
+                if let Some(repeated) = repeats.flush() {
+                    print(repeated);
+                }
+                if dropped_lines > 0 {
+                    report_dropped(dropped_lines);
+                }
+                eprintln!("{}", Diagnostic::new(Severity::Error, "Channel closed"));
                 return;
             }
-            Ok(Message::WinCh(_)) => {
+            Ok(Message::WinCh(_, _)) => {
                 #[cfg(debug_assertions)]
                 unreachable!()
             }
-            Ok(Message::Print { include_lines: _ }) => {
-                #[cfg(debug_assertions)]
-                unreachable!()
+            Ok(Message::Print { include_lines }) => {
+                // only sent in dedup mode, so a repeating line doesn't wait
+                // for a different one forever
+                if include_lines && let Some(repeated) = repeats.flush() {
+                    print(repeated);
+                }
             }
-            Ok(Message::Line {
-                text,
-                updowngroup: _,
-                leftrightgroup: _,
-                statuscode,
-            }) => {
-                // filtering. TODO: DRY
-                if !filters.is_empty() && statuscode.is_some() {
-                    let statuscode = statuscode.clone().unwrap();
-
-                    if !filters.iter().any(|x| statuscode.starts_with(x)) {
+            Ok(Message::Lines { updowngroup, lines }) => {
+                let format = formats
+                    .iter()
+                    .find(|(file, _)| **file == *updowngroup)
+                    .map(|(_, format)| *format);
+                for Line {
+                    text,
+                    statuscode,
+                    bytes: _,
+                    url,
+                    request_time: _,
+                    dropped,
+                } in lines
+                {
+                    let route = match filter.uses_routes() {
+                        true => url.map(|x| normaliser.normalise(&text[x])),
+                        false => None,
+                    };
+                    if !filter.matches(statuscode.as_deref(), route.as_deref()) {
                         continue;
                     }
+                    if dropped {
+                        dropped_lines += 1;
+                        continue;
+                    }
+                    if dropped_lines > 0 {
+                        report_dropped(dropped_lines);
+                        dropped_lines = 0;
+                    }
+                    let repeated = match dedup {
+                        true => {
+                            let key = dedup::dedup_key(&text, format, &normaliser, &keys);
+                            match repeats.add(key, text, statuscode) {
+                                Some(repeated) => repeated,
+                                None => continue,
+                            }
+                        }
+                        false => Repeated {
+                            text,
+                            statuscode,
+                            count: 1,
+                        },
+                    };
+                    print(repeated);
                 }
-                println!("{}", parse_nginx_line(&text))
             }
             Ok(Message::RegisterGroup(_)) => {
                 // shouldn't happen often
             }
+            Ok(Message::Prompt(_)) => {
+                #[cfg(debug_assertions)]
+                unreachable!()
+            }
+            Ok(Message::Filter(new_filter)) => filter = new_filter,
+            Ok(Message::Toggle(_)) | Ok(Message::Navigate(_)) => {
+                #[cfg(debug_assertions)]
+                unreachable!()
+            }
+            Ok(Message::FileStatus {
+                file,
+                state,
+                mtime: _,
+            }) => {
+                if let Some(diagnostic) = state.diagnostic(&file) {
+                    eprintln!("{diagnostic}");
+                }
+            }
+            Ok(Message::FileFormat { file, format }) => {
+                match formats.iter_mut().find(|(x, _)| *x == file) {
+                    Some((_, known)) => *known = format,
+                    None => formats.push((file, format)),
+                }
+            }
+            Ok(Message::Diagnostic(diagnostic)) => eprintln!("{diagnostic}"),
         }
     }
 }
 
-///
-/// requested_width:
-/// Some(0) = unlimited line length  -- no sigwinch handler installed
-/// Some(x) = cut off at x           -- no sigwinch handler installed
-/// None    = use screen's width     -- sigwinch handler installed
+/// The number of diagnostics kept for the log
+const MAX_LOG: usize = 100;
+
+/// How the TUI should look at startup
+pub struct TuiSettings {
+    pub target_height: u16,
+    /// Some(0) = unlimited line length  -- no sigwinch handler installed
+    /// Some(x) = cut off at x           -- no sigwinch handler installed
+    /// None    = use screen's width     -- sigwinch handler installed
+    pub requested_width: Option<u16>,
+    pub group_by: GroupBy,
+    pub modes: Modes,          // can be toggled while running
+    pub stale_after: Duration, // groups silent for this long are greyed out
+    pub scroll_step: u16,      // columns to shift the lines per arrow key
+    pub fullscreen: bool,      // use the alternate screen instead of drawing inline
+    pub sampling: Strategy,    // which lines to show when they don't all fit
+}
+
 pub async fn process_as_tui(
     channel: Receiver<Message>,
-    target_height: u16,
-    requested_width: Option<u16>,
-    filters: Vec<String>,
+    backlog: Backlog,
+    settings: TuiSettings,
+    mut filter: Filter,
+    normaliser: RouteNormaliser,
+    keys: JsonKeys,
 ) {
-    let mut pending_lines: VecDeque<(String, Option<String>)> =
-        VecDeque::with_capacity(target_height as usize);
-    let mut lines_skipped: u32 = 0;
+    let TuiSettings {
+        target_height,
+        requested_width,
+        group_by,
+        mut modes,
+        stale_after,
+        scroll_step,
+        fullscreen,
+        sampling,
+    } = settings;
+    let started = Instant::now();
+    let mut sampler = Sampler::new(sampling);
     // These are unlikely to change often, so we'll track them in memory instead
     // of recomputing them every time
     let global_statuscodes = Arc::new(Mutex::new(vec![]));
     let mut groups = GroupMap::new(global_statuscodes.clone());
+    // every route is a row, the lines need some room as well
+    let max_routes = cmp::max(1, usize::from(target_height) / 2);
 
+    let mut screen_width = terminal::get_terminal_width();
     let mut cut_width = match requested_width {
-        None => terminal::get_terminal_width(),
+        None => screen_width,
         Some(x) => x,
     };
 
-    println!("{CSI}?25l"); // hide cursor
+    // in fullscreen mode the lines are kept here, the terminal has no scrollback
+    let mut screen = fullscreen.then(|| Screen::new(screen_width, target_height));
+    // the lines above the stats that are drawn again when scrolling: in
+    // fullscreen mode the whole screen, inline what was printed since the
+    // last batch of lines, which takes up history_rows on the terminal
+    let mut history: VecDeque<ShownLine> = VecDeque::with_capacity(target_height.into());
+    let mut history_rows = 0;
+    let mut scrolled = false;
+
+    match fullscreen {
+        true => print!("{CSI}?1049h{CSI}?25l"), // switch to the alternate screen, hide cursor
+        false => println!("{CSI}?25l"),         // hide cursor
+    }
 
     let mut lastprinted_stats: String = "".to_owned(); // for optimization we want to minimize printing
     let mut lines_to_wipe = 0;
+    // the row above the stats is the "Output sampled" line, at first the row
+    // left empty when hiding the cursor
+    let mut note_above_stats = true;
+    let mut bottom_rows = 1; // rows of the stats, status bar, etc. below the lines
+    let mut prompt: Option<String> = None;
+    let mut samplerate: u32 = 100;
+    let mut files: Vec<FileInfo> = vec![]; // as registered
+    // lines that passed the filter while paused, per status class
+    let mut paused_counts: Vec<(String, u64)> = vec![];
+    let mut paused_summary: Option<String> = None; // printed once when resuming
+    let mut selected: Option<String> = None; // the group (as in the view) with the cursor
+    let mut focus: Option<FocusStats> = None;
+    let mut scroll: usize = 0; // columns hidden at the start of every line
+    let mut log: Vec<Diagnostic> = vec![]; // warnings and errors, oldest first
+    let mut dropped_lines: u64 = 0; // since the lines were last shown, see Backlog
+    let mut dropped_shown: u64 = 0; // between the lines that were shown last
 
     loop {
-        let number_of_lines = target_height - groups.len() as u16 - 2; // we'll try to show the last output line of last time at the top
-        match channel.recv().await {
+        let number_of_lines = match &screen {
+            Some(screen) => cmp::max(1, screen.height().saturating_sub(bottom_rows) as u16),
+            None => cmp::max(1, target_height.saturating_sub(bottom_rows as u16 + 1)), // we'll try to show the last output line of last time at the top
+        };
+        let (include_lines, update_stats) = match backlog.recv(&channel).await {
             Err(_) => {
-                eprintln!("Channel closed.");
+                // nothing will be drawn anymore
+                eprintln!("{}", Diagnostic::new(Severity::Error, "Channel closed"));
                 return;
             }
-            Ok(Message::WinCh(new_terminal_width)) => {
-                // we only connect the sigwinch handler when the user did not specify a width,
-                // so every WinCh signal we see meant we have to change our width
-                cut_width = new_terminal_width;
+            Ok(Message::WinCh(new_terminal_width, new_terminal_height)) => {
+                // the handler is connected when the user did not specify a width,
+                // or in fullscreen mode where we also need the height
+                if requested_width.is_none() {
+                    cut_width = new_terminal_width;
+                }
+                screen_width = new_terminal_width;
+                match screen.as_mut() {
+                    None => continue,
+                    Some(screen) => {
+                        screen.resize(new_terminal_width, new_terminal_height);
+                        (false, false)
+                    }
+                }
             }
             Ok(Message::RegisterGroup(tag)) => {
-                let _ = groups.get_or_create(tag);
+                files.push(FileInfo::new(tag.clone()));
+                if group_by == GroupBy::File {
+                    let _ = groups.get_or_create(&tag);
+                }
+                continue;
             }
-            Ok(Message::Line {
-                text,
-                updowngroup,
-                leftrightgroup,
-                statuscode,
-            }) => {
-                // accounting
-                if let Some(leftrightgroup) = leftrightgroup.clone() {
-                    let groupstats = groups.get_or_create(updowngroup.clone());
-                    let statusstats = groupstats.get_or_create(leftrightgroup).await;
-                    statusstats.pending += 1;
+            Ok(Message::Prompt(new_prompt)) => {
+                // redraw right away so typing doesn't lag, but don't feed the
+                // speedometers: the short interval would skew them
+                prompt = new_prompt;
+                (false, false)
+            }
+            Ok(Message::Toggle(toggle)) => {
+                modes.toggle(toggle);
+                if toggle == Toggle::Combine && focus.is_some() {
+                    // the focused row is gone: folded into the combined one,
+                    // or the combined one is split up into the groups again
+                    focus = modes.combine.then(|| FocusStats::new(String::new()));
+                    selected = focus.as_ref().map(|x| x.group.clone());
                 }
+                if toggle == Toggle::Pause && !modes.paused {
+                    let total: u64 = paused_counts.iter().map(|(_, count)| count).sum();
+                    let per_class: Vec<String> = paused_counts
+                        .iter()
+                        .map(|(class, count)| format!("{class}: {count}"))
+                        .collect();
+                    paused_summary = Some(format!(
+                        "-- Resumed, {total} lines arrived while paused ({})",
+                        per_class.join(", ")
+                    ));
+                    paused_counts.clear();
+                }
+                (false, false)
+            }
+            Ok(Message::Filter(new_filter)) => {
+                // the lines we kept so far were selected by the old filter
+                filter = new_filter;
+                sampler.clear();
+                continue;
+            }
+            Ok(Message::Lines { updowngroup, lines }) => {
+                let format = files
+                    .iter()
+                    .find(|x| *x.file == *updowngroup)
+                    .and_then(|x| x.format);
+                for Line {
+                    text,
+                    statuscode,
+                    bytes,
+                    url,
+                    request_time,
+                    dropped,
+                } in lines
+                {
+                    // the route is only computed when someone needs it
+                    let needs_route =
+                        group_by == GroupBy::Route || filter.uses_routes() || focus.is_some();
+                    let route = match needs_route {
+                        true => url.map(|x| normaliser.normalise(&text[x])),
+                        false => None,
+                    };
+                    let group = match (group_by, &route) {
+                        (GroupBy::Route, Some(route)) => groups.route_group(route, max_routes),
+                        _ => &updowngroup,
+                    };
 
-                // filtering. TODO: DRY
-                if !filters.is_empty() && statuscode.is_some() {
-                    let statuscode = statuscode.clone().unwrap();
+                    // accounting
+                    if let Some(statuscode) = statuscode.as_deref() {
+                        let groupstats = groups.get_or_create(group);
+                        if let Some(request_time) = request_time {
+                            groupstats.latencies.add(request_time);
+                        }
+                        let statusstats = groupstats.get_or_create(statuscode).await;
+                        statusstats.pending += 1;
+                        statusstats.pending_bytes += bytes.unwrap_or(0);
+                    }
+                    if let Some(focus) = focus.as_mut() {
+                        // when combined there's only a single group
+                        if !modes.combine && focus.group != group {
+                            continue;
+                        }
+                        focus.add(request_time, route.clone());
+                    }
 
-                    if !filters.iter().any(|x| statuscode.starts_with(x)) {
+                    if !filter.matches(statuscode.as_deref(), route.as_deref()) {
                         continue;
                     }
+                    if modes.paused {
+                        let class = statuscode
+                            .as_deref()
+                            .and_then(get_statuscode_class)
+                            .unwrap_or_else(|| "other".to_owned());
+                        match paused_counts.binary_search_by(|(x, _)| x.cmp(&class)) {
+                            Ok(index) => paused_counts[index].1 += 1,
+                            Err(index) => paused_counts.insert(index, (class, 1)),
+                        }
+                    }
+                    if dropped {
+                        // counted, but there's no text left to show
+                        dropped_lines += 1;
+                        continue;
+                    }
+                    let key = modes
+                        .dedup
+                        .then(|| dedup::dedup_key(&text, format, &normaliser, &keys));
+                    sampler.add(text, statuscode, key, number_of_lines.into());
                 }
-                if pending_lines.len() >= number_of_lines.into() {
-                    pending_lines.pop_front();
-                    lines_skipped += 1;
-                };
-                pending_lines.push_back((text, leftrightgroup));
+                continue;
             }
-            Ok(Message::Print { include_lines }) => {
-                // Printing to a terminal is _really_ slow, so if our current
-                // output would be the same as the previous output we'll skip
-                // printing
-                //
-                // This is getting a little bit tricky because we have 2
-                // different printing modes (with lines and without lines), and
-                // both could end up deciding not to print.
-                let mut toflush_lines = "".to_owned();
-                let mut toflush_stats = "".to_owned();
-
-                if include_lines && !pending_lines.is_empty() {
-                    let samplerate: u32 = match lines_skipped {
-                        0 => 100,
-                        _ => {
-                            (100 * pending_lines.len() as u32)
-                                / (lines_skipped + pending_lines.len() as u32)
-                        }
-                    };
-                    for (line, statuscode) in pending_lines.iter() {
-                        let (color, reset) = match statuscode {
-                            None => (colors::ORANGE, colors::RESET),
-                            Some(_) => ("", ""),
+            Ok(Message::Navigate(navigation)) => {
+                let view = groups.view(modes.combine, modes.merge);
+                let position = view
+                    .iter()
+                    .position(|x| Some(&x.group) == selected.as_ref());
+                match navigation {
+                    Navigation::Up => {
+                        let index = match position {
+                            None | Some(0) => view.len().saturating_sub(1),
+                            Some(x) => x - 1,
                         };
-                        let trimmed_line = if cut_width != 0 && line.len() > cut_width as usize {
-                            &line[..cut_width as usize]
-                        } else {
-                            &line[..]
+                        selected = view.get(index).map(|x| x.group.clone());
+                    }
+                    Navigation::Down => {
+                        let index = match position {
+                            Some(x) if x + 1 < view.len() => x + 1,
+                            _ => 0,
                         };
-                        toflush_lines +=
-                            &format!("{color}{}{reset}\n", parse_nginx_line(trimmed_line));
+                        selected = view.get(index).map(|x| x.group.clone());
                     }
-                    pending_lines.clear();
-                    lines_skipped = 0;
-
-                    toflush_lines += &format!("-- Output sampled at {samplerate}%\n");
-                }
-
-                let maxtagname =
-                    cmp::max(8, groups.iter().map(|x| x.group.len()).max().unwrap_or(0)); // if we have no tags we don't care about the answer
-                let shared_prefix_len = groups.shared_prefix.len();
-                let shared_suffix_len = groups.shared_suffix.len();
-                let padded_group_length = maxtagname - shared_prefix_len - shared_suffix_len;
-
-                for groupstats in groups.iter_mut() {
-                    groupstats.process();
-
-                    let padded_tag =
-                        if groupstats.group.len() <= shared_prefix_len + shared_suffix_len {
-                            "@".to_owned() + &" ".repeat(padded_group_length - 1)
-                        } else {
-                            "".to_owned()
-                                + &groupstats.group.clone()
-                                    [shared_prefix_len..groupstats.group.len() - shared_suffix_len]
-                                + &" ".repeat(maxtagname - groupstats.group.len())
+                    Navigation::Left | Navigation::Right => {
+                        // no further than the end of the widest line
+                        let widest = history.iter().map(ShownLine::width).max().unwrap_or(0);
+                        let visible = match cut_width {
+                            0 => screen_width,
+                            x => x,
                         };
-                    toflush_stats += &format!("-- {padded_tag} ");
-
-                    // This looks a bit messy, but roughly:
-                    // * global_statuscodes is a list of all status codes we have seen so far, sorted
-                    // * groupstats is a list of all status codes we have seen so far for this group, sorted
-                    //
-                    // groupstats is strictly a subset of global_statuscodes.
-                    // Since both are sorted we can iterate over them in parallel which should be quite efficient.
-                    let mut group_statusstats = groupstats.iter();
-                    let mut pending_group_statusstat = None;
-                    for statuscode in global_statuscodes.lock().await.iter() {
-                        if pending_group_statusstat.is_none() {
-                            pending_group_statusstat = group_statusstats.next()
+                        let end = widest.saturating_sub(visible.into());
+                        let previous = scroll;
+                        scroll = match navigation {
+                            Navigation::Left => scroll.saturating_sub(scroll_step.into()),
+                            _ => cmp::max(scroll, cmp::min(scroll + usize::from(scroll_step), end)),
                         };
-                        if pending_group_statusstat.is_some()
-                            && &pending_group_statusstat.unwrap().statuscode == statuscode
-                        {
-                            // This will consuming next_group_statusstat
-                            // which is needed for the next iteration
-                            let unwrapped = pending_group_statusstat.take().unwrap();
-                            let (color, reset) = code2color(&unwrapped.statuscode);
-                            toflush_stats += &format!(
-                                "{:7.1} [{color}{}{reset}] ",
-                                unwrapped.ring.get_speed(),
-                                unwrapped.statuscode,
-                            );
+                        scrolled = scroll != previous;
+                    }
+                    Navigation::Enter => {
+                        if let Some(group) = &selected {
+                            // the lines we kept so far might be from other groups
+                            focus = Some(FocusStats::new(group.clone()));
+                            sampler.clear();
+                        }
+                    }
+                    Navigation::Back => {
+                        if modes.help {
+                            modes.help = false;
+                        } else if modes.log {
+                            modes.log = false;
+                        } else if modes.heatmap {
+                            modes.heatmap = false;
+                        } else if focus.is_some() {
+                            focus = None;
                         } else {
-                            #[cfg(debug_assertions)]
-                            {
-                                toflush_stats += &format!("{:>7}  {}  ", "", statuscode);
-                            }
-                            #[cfg(not(debug_assertions))]
-                            {
-                                toflush_stats +=
-                                    &format!("{:7}  {}  ", "", " ".repeat(statuscode.len()));
-                            }
+                            selected = None;
                         }
                     }
-                    toflush_stats += "\n";
                 }
-                toflush_stats.truncate(toflush_stats.trim_end().len());
-
-                if !toflush_lines.is_empty() || toflush_stats != lastprinted_stats {
-                    // the line "Output sampled at 75%" above the stats should:
-                    // * get wiped when we want to print lines *and* there are lines
-                    // * not get wiped when we want to print lines but there were *no* lines
-                    // * not get wiped when we're only printing stats (the stats don't include this line)
-                    if include_lines {
-                        lines_to_wipe += 1;
+                (false, false)
+            }
+            Ok(Message::FileStatus { file, state, mtime }) => {
+                if let Some(info) = files.iter_mut().find(|x| x.file == file) {
+                    if info.state != state
+                        && let Some(diagnostic) = state.diagnostic(&file)
+                    {
+                        log.push(diagnostic);
                     }
+                    info.update(state, mtime);
+                }
+                // shown with the next stats
+                continue;
+            }
+            Ok(Message::FileFormat { file, format }) => {
+                if let Some(info) = files.iter_mut().find(|x| x.file == file) {
+                    info.format = Some(format);
+                }
+                continue;
+            }
+            Ok(Message::Diagnostic(diagnostic)) => {
+                log.push(diagnostic);
+                (false, false)
+            }
+            Ok(Message::Print { include_lines }) => (include_lines, true),
+        };
 
-                    let toflush_wiper = if lines_to_wipe == 0 {
-                        // special case: using CSI<n>A with n = 0 still moves
-                        // the cursor up, and we only want to move to the left
-                        // without moving upwards
-                        &format!("\r{CSI}J")
-                    } else {
-                        //          ____________________________________ move cursor to beginning of line
-                        //         |                     _______________ move cursor up X lines
-                        //         |                    |      _________ clear to end of screen
-                        //ormat!(" |                    |     |
-                        &format!("\r{CSI}{lines_to_wipe}A{CSI}J")
-                    };
-                    print!("{toflush_wiper}{toflush_lines}{toflush_stats}");
-                    std::io::stdout().flush().unwrap();
+        // Printing to a terminal is _really_ slow, so if our current
+        // output would be the same as the previous output we'll skip
+        // printing
+        //
+        // This is getting a little bit tricky because we have 2
+        // different printing modes (with lines and without lines), and
+        // both could end up deciding not to print.
+        let mut toflush: Vec<ShownLine> = paused_summary
+            .take()
+            .map(ShownLine::note)
+            .into_iter()
+            .collect();
+        let mut toflush_note = false; // toflush ends with "Output sampled"
 
-                    lines_to_wipe = toflush_stats.chars().filter(|x| *x == '\n').count(); // wipe next time
-                    lastprinted_stats = toflush_stats;
-                }
+        if include_lines && !modes.paused {
+            // like the sample rate, this is about the lines shown now
+            dropped_shown = std::mem::take(&mut dropped_lines);
+        }
+        if include_lines && !modes.paused && !sampler.is_empty() {
+            let sample = sampler.take(number_of_lines.into());
+            samplerate = sample.rate();
+            for repeated in sample.lines.iter() {
+                let (color, reset) = match repeated.statuscode {
+                    None => (colors::theme().unparsed, colors::RESET),
+                    Some(_) => ("", ""),
+                };
+                toflush.push(ShownLine {
+                    text: format!("{color}{}{reset}", highlight(&repeated.text, &keys)),
+                    count: (modes.dedup || repeated.count > 1).then_some(repeated.count),
+                    scrolls: true,
+                });
+            }
+
+            if screen.is_none() {
+                // the status bar has this as well, but in the scrollback it's good to know
+                let dropped = match dropped_shown {
+                    0 => String::new(),
+                    dropped => format!(", dropped {dropped} lines"),
+                };
+                toflush.push(ShownLine::note(match sample.rate_per_class().as_str() {
+                    "" => format!("-- Output sampled at {samplerate}%{dropped}"),
+                    per_class => {
+                        format!("-- Output sampled at {samplerate}% ({per_class}){dropped}")
+                    }
+                }));
+                toflush_note = true;
+            }
+        }
+
+        if update_stats {
+            for groupstats in groups.iter_mut() {
+                groupstats.process();
+            }
+        }
+
+        let mut toflush_stats = "".to_owned();
+        let maxtagname = cmp::max(8, groups.iter().map(|x| x.group.len()).max().unwrap_or(0)); // if we have no tags we don't care about the answer
+        let shared_prefix_len = groups.shared_prefix.len();
+        let shared_suffix_len = groups.shared_suffix.len();
+        let padded_group_length = maxtagname - shared_prefix_len - shared_suffix_len;
+        let view = groups.view(modes.combine, modes.merge);
+        let view_statuscodes = groups.view_statuscodes(modes.merge).await;
+        let view_severities = groups.view_severities();
+
+        let mut rows = vec![];
+        for groupview in view.iter() {
+            let padded_tag = if groupview.group.len() <= shared_prefix_len + shared_suffix_len {
+                "@".to_owned() + &" ".repeat(padded_group_length - 1)
+            } else {
+                "".to_owned()
+                    + &groupview.group[shared_prefix_len..groupview.group.len() - shared_suffix_len]
+                    + &" ".repeat(maxtagname - groupview.group.len())
+            };
+            let file = match (group_by, modes.combine) {
+                (GroupBy::File, false) => files.iter().find(|x| x.file == groupview.group),
+                _ => None,
+            };
+            let last_line = groupview.last_line.map(|x| x.elapsed());
+            // groups that never had a line are judged by their file instead
+            let silent_for = last_line
+                .or_else(|| file.and_then(|x| x.mtime?.elapsed().ok()))
+                .unwrap_or_else(|| started.elapsed());
+            rows.push(StatsRow {
+                tag: padded_tag,
+                groupview,
+                selected: Some(&groupview.group) == selected.as_ref(),
+                stale: silent_for > stale_after,
+                freshness: render::freshness(last_line, file),
+            });
+        }
+        toflush_stats += &match modes.bars {
+            true => render::bar_chart(&rows, screen_width.into()),
+            false => render::stats_table(
+                &rows,
+                &view_statuscodes,
+                &view_severities,
+                screen_width.into(),
+            ),
+        };
+        toflush_stats.truncate(toflush_stats.trim_end().len());
+
+        if log.len() > MAX_LOG {
+            log.drain(..log.len() - MAX_LOG);
+        }
+        if modes.help {
+            // the help replaces the stats
+            toflush_stats = render::help(&modes, &filter, group_by, sampling, &files);
+        } else if modes.log {
+            // and so does the log
+            toflush_stats = render::log(&log);
+        } else if modes.heatmap {
+            // and the heatmap
+            let group = selected.as_deref().filter(|_| !modes.combine);
+            let columns = groups.latencies(group);
+            let label = group.unwrap_or("all groups");
+            toflush_stats = render::heatmap(label, &columns, screen_width.into());
+        } else if let Some(focus) = &focus {
+            // so does the focus, with all status codes instead of merged ones
+            let label = match focus.group.is_empty() {
+                true => "all groups",
+                false => &focus.group,
+            };
+            let view = groups.view(modes.combine, false);
+            let groupview = view
+                .iter()
+                .find(|x| modes.combine || x.group == focus.group);
+            toflush_stats = render::focus(label, groupview, focus);
+        }
+        if !toflush_stats.is_empty() {
+            toflush_stats += "\n";
+        }
+        if let Some(diagnostic) = log.last()
+            && diagnostic.severity >= Severity::Warning
+            && diagnostic.at.elapsed() < Duration::from_secs(10)
+            && !modes.log
+        {
+            // the most recent problem gets a line of its own for a while
+            toflush_stats += &render::diagnostic(diagnostic);
+            toflush_stats += "\n";
+        }
+        let paused_lines = paused_counts.iter().map(|(_, count)| count).sum();
+        let status = Status {
+            samplerate,
+            strategy: sampling,
+            paused_lines,
+            dropped_lines: dropped_shown,
+            scroll,
+            messages: log.len(),
+        };
+        toflush_stats += &render::status_bar(&modes, &filter, &status);
+        if let Some(prompt) = &prompt {
+            // the cursor is hidden, so we draw our own
+            let cursor = colors::theme().highlight;
+            toflush_stats += &format!("\n{prompt}{cursor} {}", colors::RESET);
+        }
+
+        if let Some(screen) = screen.as_mut() {
+            // the lines fill the screen from the top, the stats stick to the bottom
+            history.extend(toflush);
+            while history.len() > screen.height() {
+                history.pop_front();
+            }
+            let bottom: Vec<&str> = toflush_stats.split('\n').collect();
+            let lines_region = screen.height().saturating_sub(bottom.len());
+            let mut rows: Vec<String> = history
+                .iter()
+                .skip(history.len().saturating_sub(lines_region))
+                .map(|x| x.draw(scroll, cut_width.into()))
+                .collect();
+            rows.resize(lines_region, "".to_owned());
+            rows.extend(bottom.iter().map(|x| x.to_string()));
+            let output = screen.draw(&rows);
+            if !output.is_empty() {
+                print!("{output}");
+                std::io::stdout().flush().unwrap();
             }
+            bottom_rows = bottom.len();
+            continue;
+        }
+
+        // the line "Output sampled at 75%" above the stats should:
+        // * get wiped when a new one is printed below the new lines
+        // * not get wiped when we want to print lines but there were *no* lines
+        //   (fe. while paused)
+        // * not get wiped when we're only printing stats (the stats don't include this line)
+        // * not get wiped when other lines were printed below it
+        if toflush_note && note_above_stats {
+            lines_to_wipe += 1;
+        }
+        if !toflush.is_empty() {
+            note_above_stats = toflush_note;
+        }
+        if toflush_note {
+            // a new batch, the previous one stays in the scrollback as it was
+            history.clear();
+            history_rows = 0;
+        }
+        // wiping moves the cursor up, which stops at the top of the screen
+        if std::mem::take(&mut scrolled) && history_rows + bottom_rows <= target_height.into() {
+            lines_to_wipe += history_rows;
+            history_rows = 0;
+            toflush.splice(0..0, history.drain(..));
+        }
+        let mut toflush_lines = String::new();
+        for line in toflush {
+            let drawn = line.draw(scroll, cut_width.into());
+            history_rows += terminal::screen_rows(&drawn, screen_width);
+            toflush_lines += &drawn;
+            toflush_lines += "\n";
+            history.push_back(line);
+        }
+
+        if !toflush_lines.is_empty() || toflush_stats != lastprinted_stats {
+            let toflush_wiper = if lines_to_wipe == 0 {
+                // special case: using CSI<n>A with n = 0 still moves
+                // the cursor up, and we only want to move to the left
+                // without moving upwards
+                &format!("\r{CSI}J")
+            } else {
+                //          ____________________________________ move cursor to beginning of line
+                //         |                     _______________ move cursor up X lines
+                //         |                    |      _________ clear to end of screen
+                //ormat!(" |                    |     |
+                &format!("\r{CSI}{lines_to_wipe}A{CSI}J")
+            };
+            print!("{toflush_wiper}{toflush_lines}{toflush_stats}");
+            std::io::stdout().flush().unwrap();
+
+            // wipe next time, including the rows the terminal wrapped
+            lines_to_wipe = terminal::screen_rows(&toflush_stats, screen_width) - 1;
+            bottom_rows = lines_to_wipe + 1;
+            lastprinted_stats = toflush_stats;
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::FileState;
+    use crate::Line;
     use crate::Message;
+    use crate::backlog::Backlog;
     use crate::follow;
-    use crate::get_statuscode_class;
+    use crate::json::JsonKeys;
     use smol::LocalExecutor;
     use smol::Timer;
     use smol::future;
@@ -640,7 +1425,7 @@ mod tests {
     use std::path::PathBuf;
     use std::process::Command;
     use std::str::from_utf8;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
     use std::{fs::File, io::Write};
 
     struct TempFile {
@@ -659,18 +1444,36 @@ mod tests {
                 .output()
                 .expect("Failed to run mktemp")
                 .stdout;
-            let filename = from_utf8(&stdout.strip_suffix(b"\n").unwrap())
+            let filename = from_utf8(stdout.strip_suffix(b"\n").unwrap())
                 .expect("Failed to interpret mktemp output")
                 .to_owned();
             let file = File::options()
                 .read(true)
                 .write(true)
                 .open(PathBuf::from(filename.clone()))
-                .expect(&format!("Failed to open tmpfile '{filename}'"));
+                .unwrap_or_else(|_| panic!("Failed to open tmpfile '{filename}'"));
             TempFile { filename, file }
         }
     }
 
+    /// What follow sends for lines without status codes that were read at once
+    fn batch(tmpfile: &TempFile, texts: &[&str]) -> Message {
+        Message::Lines {
+            updowngroup: tmpfile.filename.as_str().into(),
+            lines: texts
+                .iter()
+                .map(|x| Line {
+                    text: x.to_string(),
+                    statuscode: None,
+                    bytes: None,
+                    url: None,
+                    request_time: None,
+                    dropped: false,
+                })
+                .collect(),
+        }
+    }
+
     #[test]
     fn test_reading_files() {
         let local_ex = LocalExecutor::new();
@@ -684,13 +1487,9 @@ mod tests {
 
             let (sender, receiver) = smol::channel::bounded(10000);
 
-            smol::spawn(follow(
-                sender,
-                tmpfile.filename.clone().into(),
-                tmpfile.filename.clone(),
-                get_statuscode_class,
-            ))
-            .detach();
+            let backlog = Backlog::default();
+            let path = tmpfile.filename.clone().into();
+            smol::spawn(follow(sender, backlog, path, None, JsonKeys::default())).detach();
 
             // No data written yet
             Timer::after(Duration::from_millis(70)).await;
@@ -709,15 +1508,7 @@ mod tests {
             // One whole line written
             file.write_all(b"line 3\n").unwrap();
             Timer::after(Duration::from_millis(70)).await;
-            assert_eq!(
-                receiver.try_recv().unwrap(),
-                Message::Line {
-                    text: "line 3".to_owned(),
-                    updowngroup: tmpfile.filename.clone(),
-                    leftrightgroup: None,
-                    statuscode: None,
-                },
-            );
+            assert_eq!(receiver.try_recv().unwrap(), batch(&tmpfile, &["line 3"]));
 
             // One line written in 2 separate parts
             // First bit...
@@ -735,12 +1526,7 @@ mod tests {
             Timer::after(Duration::from_millis(70)).await;
             assert_eq!(
                 receiver.try_recv().unwrap(),
-                Message::Line {
-                    text: "line 4... and a bit".to_owned(),
-                    updowngroup: tmpfile.filename.clone(),
-                    leftrightgroup: None,
-                    statuscode: None,
-                },
+                batch(&tmpfile, &["line 4... and a bit"]),
             );
 
             // Two lines written at once
@@ -748,21 +1534,7 @@ mod tests {
             Timer::after(Duration::from_millis(70)).await;
             assert_eq!(
                 receiver.try_recv().unwrap(),
-                Message::Line {
-                    text: "line 5".to_owned(),
-                    updowngroup: tmpfile.filename.clone(),
-                    leftrightgroup: None,
-                    statuscode: None,
-                },
-            );
-            assert_eq!(
-                receiver.try_recv().unwrap(),
-                Message::Line {
-                    text: "line 6".to_owned(),
-                    updowngroup: tmpfile.filename.clone(),
-                    leftrightgroup: None,
-                    statuscode: None,
-                }
+                batch(&tmpfile, &["line 5", "line 6"]),
             );
 
             // Three and a half lines at once
@@ -770,32 +1542,129 @@ mod tests {
             Timer::after(Duration::from_millis(70)).await;
             assert_eq!(
                 receiver.try_recv().unwrap(),
-                Message::Line {
-                    text: "line 7".to_owned(),
-                    updowngroup: tmpfile.filename.clone(),
-                    leftrightgroup: None,
-                    statuscode: None,
-                },
-            );
-            assert_eq!(
-                receiver.try_recv().unwrap(),
-                Message::Line {
-                    text: "line 8".to_owned(),
-                    updowngroup: tmpfile.filename.clone(),
-                    leftrightgroup: None,
-                    statuscode: None,
-                },
+                batch(&tmpfile, &["line 7", "line 8", "line 9"]),
             );
+            assert!(receiver.try_recv().is_err());
+        }));
+    }
+    /// Collects the states follow reports and the texts of the lines until
+    /// `last` comes by. A state is reported again when the mtime changed,
+    /// that's left out.
+    async fn receive_until(
+        receiver: &smol::channel::Receiver<Message>,
+        received: &mut Vec<String>,
+        last: &str,
+    ) {
+        let start = Instant::now();
+        while received.last().map(String::as_str) != Some(last)
+            && start.elapsed() < Duration::from_secs(5)
+        {
+            Timer::after(Duration::from_millis(10)).await;
+            while let Ok(message) = receiver.try_recv() {
+                match message {
+                    Message::FileStatus { state, .. } => {
+                        let state = match state {
+                            FileState::Unreadable(_) => "Unreadable".to_owned(),
+                            state => format!("{state:?}"),
+                        };
+                        if received.last() != Some(&state) {
+                            received.push(state);
+                        }
+                    }
+                    Message::Lines { lines, .. } => {
+                        received.extend(lines.into_iter().map(|x| x.text));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_states() {
+        let local_ex = LocalExecutor::new();
+
+        future::block_on(local_ex.run(async {
+            let tmpfile = TempFile::new();
+            let filename = tmpfile.filename.clone();
+            remove_file(&filename).unwrap();
+
+            let (sender, receiver) = smol::channel::bounded(10000);
+            let path = filename.clone().into();
+            smol::spawn(follow(
+                sender,
+                Backlog::default(),
+                path,
+                None,
+                JsonKeys::default(),
+            ))
+            .detach();
+            let mut received = vec![];
+
+            // not there yet
+            receive_until(&receiver, &mut received, "Unreadable").await;
+
+            // created, what's in it already is skipped
+            std::fs::write(&filename, "old line\n").unwrap();
+            receive_until(&receiver, &mut received, "Ok").await;
+
+            // moved away, we keep reading it
+            let moved = format!("{filename}.1");
+            std::fs::rename(&filename, &moved).unwrap();
+            receive_until(&receiver, &mut received, "Missing").await;
+
+            // a new file, read from the start
+            std::fs::write(&filename, "new line 1\nnew line 2\n").unwrap();
+            receive_until(&receiver, &mut received, "new line 2").await;
+            remove_file(&moved).unwrap();
+
             assert_eq!(
-                receiver.try_recv().unwrap(),
-                Message::Line {
-                    text: "line 9".to_owned(),
-                    updowngroup: tmpfile.filename.clone(),
-                    leftrightgroup: None,
-                    statuscode: None,
-                },
+                received,
+                [
+                    "Unreadable",
+                    "Ok",
+                    "Missing",
+                    "Rotated",
+                    "new line 1",
+                    "new line 2"
+                ]
             );
-            assert!(receiver.try_recv().is_err());
+        }));
+    }
+
+    #[test]
+    fn test_roundabout_path() {
+        let local_ex = LocalExecutor::new();
+
+        future::block_on(local_ex.run(async {
+            // the same file as the one it points to, not a rotated one
+            let tmpfile = TempFile::new();
+            let filename = PathBuf::from(&tmpfile.filename);
+            let dir = filename.parent().unwrap();
+            let path = dir.join("..").join(dir.file_name().unwrap());
+            let path = path.join(filename.file_name().unwrap());
+
+            let (sender, receiver) = smol::channel::bounded(10000);
+            smol::spawn(follow(
+                sender,
+                Backlog::default(),
+                path,
+                None,
+                JsonKeys::default(),
+            ))
+            .detach();
+            let mut received = vec![];
+            receive_until(&receiver, &mut received, "Ok").await;
+
+            let mut file = &tmpfile.file;
+            file.write_all(b"line 1\n").unwrap();
+            receive_until(&receiver, &mut received, "line 1").await;
+            // a few times at the end of the file
+            Timer::after(Duration::from_millis(200)).await;
+            file.write_all(b"line 2\n").unwrap();
+            receive_until(&receiver, &mut received, "line 2").await;
+
+            assert_eq!(received, ["Ok", "line 1", "line 2"]);
         }));
     }
 }
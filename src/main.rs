@@ -8,19 +8,30 @@ use std::{path::PathBuf, time::Duration};
 
 use smol::LocalExecutor;
 use smol::future;
-use smol::{Timer, channel::bounded};
+use smol::{Timer, channel::unbounded};
 
 use nginx_tail::Error;
+use nginx_tail::GroupBy;
 use nginx_tail::Message;
+use nginx_tail::Modes;
 use nginx_tail::SenderChannel;
+use nginx_tail::TuiSettings;
+use nginx_tail::backlog::Backlog;
+use nginx_tail::diagnostics::{Diagnostic, Severity};
+use nginx_tail::filter::Filter;
 use nginx_tail::follow;
-use nginx_tail::get_statuscode_class;
+use nginx_tail::format::Overrides;
+use nginx_tail::json::JsonKeys;
 use nginx_tail::keyboard_reader;
 use nginx_tail::periodic_print;
 use nginx_tail::process_as_streaming;
 use nginx_tail::process_as_tui;
+use nginx_tail::routes::RouteNormaliser;
+use nginx_tail::sampling::Strategy;
 use nginx_tail::terminal::DroppableTermios;
+use nginx_tail::terminal::colors;
 use nginx_tail::terminal::colors::CSI;
+use nginx_tail::terminal::colors::{ColorDepth, Theme};
 use nginx_tail::terminal::get_terminal_height;
 use nginx_tail::terminal::get_terminal_width;
 
@@ -32,14 +43,55 @@ const HELP: &str = r#"
         -h, --help               Show this help message
             --max-width X        Cut lines to this length X.
                                  Defaults to "screen width", set to 0 for unlimited
+            --scroll-step X      Columns to scroll the lines with the left/right arrow keys.
+                                 Defaults to 20
             --target-height      Target window height.
                                  Will be met if the log lines fit in the width of your terminal
+            --fullscreen         Use the whole terminal with the stats at the bottom, like top.
+                                 The lines won't end up in the scrollback of your terminal
             --max-runtime X      Terminate after X seconds
-            --combine            Combine stats of all files together
-            --merge              Combine http statuscodes in groups
+            --combine            Combine stats of all files together.
+                                 Can be toggled with [c] while running
+            --merge              Combine http statuscodes in groups.
+                                 Can be toggled with [m] while running
+            --dedup              Show lines that only differ in their timestamp, client port,
+                                 timings or IDs in the URL once, with a count like uniq -c.
+                                 Can be toggled with [u] while running
+            --group-by X         Show stats per "file" (default) or per "route".
+                                 Routes that don't fit on the screen are counted as "other"
+            --route-rule X       Extra rewrite rule for routes as PATTERN=REPLACEMENT,
+                                 fe. "^/static/.*=/static/*". Split on the first =, write
+                                 \= for an = in the pattern. Can be used multiple times.
+                                 By default numbers, UUIDs and hashes in URLs are replaced
+                                 by :int, :uuid and :hex and query strings are dropped.
             --filter X           Only show log lines matching this status code.
                                  Can be used multiple times, "4xx" can be used to show 403, 404 etc.
+                                 "route=/users/:int" only shows lines for that route,
+                                 a trailing * matches any route starting with the text.
                                  The statistics are not affected by this option.
+            --sample X           Which lines to show when they arrive faster than they fit:
+                                 "newest" (default), "reservoir" (a random pick over the
+                                 whole interval), "stratified" (a random pick per status
+                                 class, every class gets at least one line) or
+                                 "errors-first" (5xx, then 4xx, then the rest)
+            --json-key X         For log_format ... escape=json: the key holding a field as
+                                 FIELD=KEY, fe. "status=http_status". FIELD is one of status,
+                                 request, request_uri, request_time or body_bytes_sent, by
+                                 default the keys are named like those. Can be used multiple times.
+            --format X           The format of a file as FILE=FORMAT, or FORMAT for all files.
+                                 FORMAT is one of combined, common, custom, json, error_log,
+                                 vhost_combined (Apache), caddy, haproxy, traefik_clf or
+                                 traefik_json. Apache's combined and common are nginx' ones.
+                                 FILE matches the end of a path, fe. "site1/access.log".
+                                 By default it's detected from the first lines of the file.
+                                 Can be used multiple times
+            --stale-after X      Grey out groups that had no new lines for X seconds.
+                                 Defaults to 60
+            --theme X            "dark" (default), "light" or "monochrome".
+                                 256 colors or truecolor are used when $TERM or $COLORTERM
+                                 say the terminal supports them
+            --color X            "auto" (default), "always" or "never". With auto there are
+                                 only colors when writing to a terminal and $NO_COLOR is unset
 "#;
 
 #[derive(Debug)]
@@ -53,10 +105,20 @@ struct AppArgs {
     target_height: u16,
     combine_filestats: bool,
     merge_statuscodes: bool,
+    dedup: bool,
     max_runtime: Option<u32>,
     requested_width: Option<u16>,
-    filters: Vec<String>,
+    stale_after: u32,
+    scroll_step: u16,
+    filter: Filter,
+    group_by: GroupBy,
+    sampling: Strategy,
+    formats: Overrides,
+    json_keys: JsonKeys,
+    normaliser: RouteNormaliser,
     streaming_output: bool,
+    color: bool,
+    fullscreen: bool,
 }
 
 fn main() {
@@ -83,15 +145,111 @@ fn main() {
             None
         };
 
+    let stale_after: u32 = pargs
+        .opt_value_from_str("--stale-after")
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid --stale-after: {err}");
+            process::exit(1)
+        })
+        .unwrap_or(60);
+
+    let scroll_step: u16 = pargs
+        .opt_value_from_str("--scroll-step")
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid --scroll-step: {err}");
+            process::exit(1)
+        })
+        .unwrap_or(20);
+
+    let theme_name: String = pargs
+        .opt_value_from_str("--theme")
+        .unwrap_or(None)
+        .unwrap_or_else(|| "dark".to_owned());
+    let streaming_output = !std::io::stdout().is_terminal();
+    let color = match pargs.opt_value_from_str::<&str, String>("--color") {
+        Ok(None) => None,
+        Ok(Some(x)) if x == "auto" => None,
+        Ok(Some(x)) if x == "always" => Some(true),
+        Ok(Some(x)) if x == "never" => Some(false),
+        _ => {
+            eprintln!("--color should be \"auto\", \"always\" or \"never\"");
+            process::exit(1)
+        }
+    };
+    // https://no-color.org/
+    let no_color = std::env::var("NO_COLOR").is_ok_and(|x| !x.is_empty());
+    let color = color.unwrap_or(!streaming_output && !no_color);
+    let depth = ColorDepth::detect(
+        std::env::var("COLORTERM").ok().as_deref(),
+        std::env::var("TERM").ok().as_deref(),
+    );
+    let theme = Theme::new(&theme_name, depth).unwrap_or_else(|err| {
+        eprintln!("Invalid --theme: {err}");
+        process::exit(1)
+    });
+    colors::set_theme(match color {
+        true => theme,
+        false => &Theme::MONOCHROME,
+    });
+
+    let fullscreen: bool = pargs.contains("--fullscreen");
     let combine_filestats: bool = pargs.contains("--combine");
     let merge_statuscodes: bool = pargs.contains("--merge");
+    let dedup: bool = pargs.contains("--dedup");
+
+    let mut filter = Filter::default();
+    while let Ok(term) = pargs.value_from_str::<&str, String>("--filter") {
+        filter.add_term(&term).unwrap_or_else(|err| {
+            eprintln!("Invalid --filter: {err}");
+            process::exit(1)
+        });
+    }
+
+    let sampling = match pargs.opt_value_from_str::<&str, String>("--sample") {
+        Ok(None) => Strategy::default(),
+        Ok(Some(x)) => Strategy::parse(&x).unwrap_or_else(|err| {
+            eprintln!("Invalid --sample: {err}");
+            process::exit(1)
+        }),
+        Err(err) => {
+            eprintln!("Invalid --sample: {err}");
+            process::exit(1)
+        }
+    };
+
+    let group_by = match pargs.opt_value_from_str::<&str, String>("--group-by") {
+        Ok(None) => GroupBy::File,
+        Ok(Some(x)) if x == "file" => GroupBy::File,
+        Ok(Some(x)) if x == "route" => GroupBy::Route,
+        _ => {
+            eprintln!("--group-by should be either \"file\" or \"route\"");
+            process::exit(1)
+        }
+    };
+
+    let mut json_keys = JsonKeys::default();
+    while let Ok(mapping) = pargs.value_from_str::<&str, String>("--json-key") {
+        json_keys.add(&mapping).unwrap_or_else(|err| {
+            eprintln!("Invalid --json-key: {err}");
+            process::exit(1)
+        });
+    }
 
-    let mut filters = vec![];
-    while let Ok(filter) = pargs.value_from_str::<&str, String>("--filter") {
-        filters.push(filter.trim_end_matches("x").to_owned());
+    let mut formats = Overrides::default();
+    while let Ok(format) = pargs.value_from_str::<&str, String>("--format") {
+        formats.add(&format).unwrap_or_else(|err| {
+            eprintln!("Invalid --format: {err}");
+            process::exit(1)
+        });
+    }
+
+    let mut normaliser = RouteNormaliser::new();
+    while let Ok(rule) = pargs.value_from_str::<&str, String>("--route-rule") {
+        normaliser.add_rule(&rule).unwrap_or_else(|err| {
+            eprintln!("Invalid --route-rule: {err}");
+            process::exit(1)
+        });
     }
-    filters.sort();
-    filters.dedup();
 
     #[cfg(debug_assertions)]
     let fast_generator = pargs.contains("--fast");
@@ -130,10 +288,20 @@ fn main() {
         target_height,
         combine_filestats,
         merge_statuscodes,
+        dedup,
         max_runtime,
         requested_width,
-        filters,
-        streaming_output: !std::io::stdout().is_terminal(),
+        stale_after,
+        scroll_step,
+        filter,
+        group_by,
+        sampling,
+        formats,
+        json_keys,
+        normaliser,
+        streaming_output,
+        color,
+        fullscreen,
     };
 
     match smol::block_on(innermain(args)) {
@@ -142,20 +310,27 @@ fn main() {
     }
 }
 
+/// Sends a diagnostic to the processor, which outlives everything that reports
+fn report(channel: &SenderChannel, severity: Severity, text: impl Into<String>) {
+    let diagnostic = Diagnostic::new(severity, text);
+    channel.try_send(Message::Diagnostic(diagnostic)).unwrap();
+}
+
 async fn sigwinch_handler(channel: SenderChannel) {
     let window_changed_size = Arc::new(AtomicBool::new(false));
     let Ok(_) = signal_hook::flag::register(
         signal_hook::consts::SIGWINCH,
         Arc::clone(&window_changed_size),
     ) else {
-        eprintln!("Failed to register signal handler for SIGWINCH");
+        let text = "Failed to register signal handler for SIGWINCH";
+        report(&channel, Severity::Warning, text);
         return;
     };
 
     loop {
-        if window_changed_size.load(std::sync::atomic::Ordering::Relaxed)
+        if window_changed_size.swap(false, std::sync::atomic::Ordering::Relaxed)
             && channel
-                .send(Message::WinCh(get_terminal_width()))
+                .send(Message::WinCh(get_terminal_width(), get_terminal_height()))
                 .await
                 .is_err()
         {
@@ -166,15 +341,23 @@ async fn sigwinch_handler(channel: SenderChannel) {
     }
 }
 
-async fn sigint_handler(termios: Option<DroppableTermios>) {
+async fn sigint_handler(
+    channel: SenderChannel,
+    termios: Option<DroppableTermios>,
+    fullscreen: bool,
+) {
     let terminated = Arc::new(AtomicBool::new(false));
     let Ok(_) = signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&terminated))
     else {
-        eprintln!("Failed to register signal handler for INT");
+        let text = "Failed to register signal handler for INT";
+        report(&channel, Severity::Error, text);
         return;
     };
     loop {
         if terminated.load(std::sync::atomic::Ordering::Relaxed) {
+            if fullscreen {
+                print!("{CSI}?1049l"); // back to the original screen
+            }
             println!("{CSI}?25h\nBye"); // show cursor
             if let Some(termios) = termios {
                 drop(termios); // restore terminal settings
@@ -186,8 +369,10 @@ async fn sigint_handler(termios: Option<DroppableTermios>) {
 }
 
 async fn innermain(args: AppArgs) -> Result<(), Error> {
-    // channel to send messages to the processing thread
-    let (sender, receiver) = bounded(1_000_000);
+    // channel to send messages to the processing thread, the lines in it are
+    // kept below a number of bytes by the backlog instead
+    let (sender, receiver) = unbounded();
+    let backlog = Backlog::default();
     let async_exec = LocalExecutor::new();
     let mut logfiles_to_follow = vec![];
 
@@ -195,7 +380,8 @@ async fn innermain(args: AppArgs) -> Result<(), Error> {
         if !log_file.is_file() {
             // things can still go wrong (if the file isn't readable or something)
             // but at least we tried our best
-            eprintln!("WARNING: Log file {log_file:?} is not a file");
+            let text = format!("Log file {log_file:?} is not a file");
+            report(&sender, Severity::Warning, text);
         } else {
             logfiles_to_follow.push(log_file);
         }
@@ -210,19 +396,31 @@ async fn innermain(args: AppArgs) -> Result<(), Error> {
 
         match read_dir(dir_to_check.clone()) {
             Err(e) => {
-                println!("WARNING: Failed to read directory {dir_to_check:?}: {e}");
+                let text = format!("Failed to read directory {dir_to_check:?}: {e}");
+                report(&sender, Severity::Warning, text);
                 continue;
             }
             Ok(entries) => {
                 for entry in entries {
                     match entry {
-                        Err(x) => eprintln!("Failed to process: {x}"),
+                        Err(x) => report(
+                            &sender,
+                            Severity::Warning,
+                            format!("Failed to process: {x}"),
+                        ),
                         Ok(entry) => match entry.metadata() {
-                            Err(x) => eprintln!("Failed to process: {entry:?}: {x}"),
+                            Err(x) => report(
+                                &sender,
+                                Severity::Warning,
+                                format!("Failed to process: {entry:?}: {x}"),
+                            ),
                             Ok(meta) => {
                                 if meta.is_dir() {
                                     dirs_to_check.push(entry.path());
-                                } else if meta.is_file() && (entry.file_name() == "access.log") {
+                                } else if meta.is_file()
+                                    && (entry.file_name() == "access.log"
+                                        || entry.file_name() == "error.log")
+                                {
                                     println!("Added {:?} as reader", entry.path());
                                     logfiles_to_follow.push(entry.path());
                                 }
@@ -235,25 +433,29 @@ async fn innermain(args: AppArgs) -> Result<(), Error> {
     }
 
     if logfiles_to_follow.is_empty() {
+        // no processor will run to show the warnings
+        while let Ok(Message::Diagnostic(diagnostic)) = receiver.try_recv() {
+            eprintln!("{diagnostic}");
+        }
         return Err(Error("No useable log files found".to_string()));
     }
 
     logfiles_to_follow.sort();
     logfiles_to_follow.dedup();
 
+    for file in args.formats.unused(&logfiles_to_follow) {
+        let text = format!("--format {} matches none of the files", file.display());
+        report(&sender, Severity::Warning, text);
+    }
     for log_file in logfiles_to_follow {
+        let format = args.formats.get(&log_file);
         async_exec
             .spawn(follow(
                 sender.clone(),
+                backlog.clone(),
                 log_file.clone(),
-                match args.combine_filestats {
-                    true => "".to_owned(),
-                    false => log_file.display().to_string(),
-                },
-                match args.merge_statuscodes {
-                    false => |x| Some(x.to_owned()),
-                    true => get_statuscode_class,
-                },
+                format,
+                args.json_keys.clone(),
             ))
             .detach();
     }
@@ -264,45 +466,90 @@ async fn innermain(args: AppArgs) -> Result<(), Error> {
             use nginx_tail::fake_fast;
 
             println!("Fast generator enabled");
-            async_exec.spawn(fake_fast(sender.clone())).detach();
+            async_exec
+                .spawn(fake_fast(sender.clone(), backlog.clone()))
+                .detach();
         }
         if args.slow_generator {
             use nginx_tail::fake_slow;
 
             println!("Slow generator enabled");
-            async_exec.spawn(fake_slow(sender.clone())).detach();
+            async_exec
+                .spawn(fake_slow(sender.clone(), backlog.clone()))
+                .detach();
         }
     }
 
-    if args.max_runtime.is_some() {
-        let max_runtime = args.max_runtime.unwrap();
+    let streaming_output = args.streaming_output;
+    if let Some(max_runtime) = args.max_runtime {
         async_exec
             .spawn(async move {
                 Timer::after(Duration::from_secs(max_runtime.into())).await;
-                std::process::exit(0);
+                if streaming_output {
+                    std::process::exit(0);
+                }
+                // the sigint handler restores the terminal
+                let _ = signal_hook::low_level::raise(signal_hook::consts::SIGINT);
             })
             .detach();
     }
 
     if args.streaming_output {
         // just syntax highlighting (and filtering)
-        future::block_on(async_exec.run(process_as_streaming(receiver, args.filters)))
+        if args.dedup {
+            // repeated lines are held back until a different one comes by
+            async_exec.spawn(periodic_print(sender.clone())).detach();
+        }
+        future::block_on(async_exec.run(process_as_streaming(
+            receiver,
+            backlog,
+            args.filter,
+            args.normaliser,
+            args.json_keys,
+            args.color,
+            args.dedup,
+        )))
     } else {
         // terminal with live updating stats
         let original = nginx_tail::terminal::activate_raw_mode();
-        async_exec.spawn(sigint_handler(original.ok())).detach();
+        async_exec
+            .spawn(sigint_handler(
+                sender.clone(),
+                original.ok(),
+                args.fullscreen,
+            ))
+            .detach();
 
-        if args.requested_width.is_none() {
+        if args.requested_width.is_none() || args.fullscreen {
             async_exec.spawn(sigwinch_handler(sender.clone())).detach();
         };
         async_exec.spawn(periodic_print(sender.clone())).detach();
-        async_exec.spawn(keyboard_reader(sender.clone())).detach();
+        async_exec
+            .spawn(keyboard_reader(sender.clone(), args.filter.clone()))
+            .detach();
 
+        let settings = TuiSettings {
+            target_height: args.target_height,
+            requested_width: args.requested_width,
+            group_by: args.group_by,
+            modes: Modes {
+                combine: args.combine_filestats,
+                merge: args.merge_statuscodes,
+                dedup: args.dedup,
+                ..Default::default()
+            },
+            stale_after: Duration::from_secs(args.stale_after.into()),
+            scroll_step: args.scroll_step,
+            fullscreen: args.fullscreen,
+            sampling: args.sampling,
+        };
         future::block_on(async_exec.run(process_as_tui(
             receiver,
-            args.target_height,
-            args.requested_width,
-            args.filters,
+            backlog,
+            settings,
+            args.filter,
+            args.normaliser,
+            args.json_keys,
         )));
     }
 
@@ -1,138 +1,254 @@
 use std::fmt::Display;
 
+use crate::json::{self, JsonKeys, JsonLine};
 use crate::terminal::colors;
 
+/// An access log line cut up for highlighting. Every field is a slice of the
+/// line, so cutting it up doesn't allocate.
 #[derive(PartialEq, Debug)]
-pub struct ParsedLine {
+pub struct ParsedLine<'a> {
     // <field>            the field itself
     // <field1>_<field2>  the data between field1 and field2
-    head: String,
-    head_date: Option<String>,
-    date: String,
-    date_method: Option<String>,
-    method: String,
-    method_url: Option<String>,
-    url: String,
-    url_lvl: Option<String>,
-    protocollvl: String,
-    lvl_statuscode: Option<String>,
-    statuscode: String,
-    tail: String,
+    head: &'a str,
+    head_date: Option<&'a str>,
+    date: &'a str,
+    date_method: Option<&'a str>,
+    method: &'a str,
+    method_url: Option<&'a str>,
+    url: &'a str,
+    url_lvl: Option<&'a str>,
+    protocollvl: &'a str,
+    lvl_statuscode: Option<&'a str>,
+    statuscode: &'a str,
+    tail: &'a str,
 }
 
-pub fn parse_nginx_line(line: &str) -> ParsedLine {
-    // Has to be able to parse a partial line!
-    // Take special consideration whether you've seen separator symbols:
-    let mut head = "".to_owned();
-    let mut head_date = None;
-    let mut date = "".to_owned();
-    let mut date_method = None;
-    let mut method = "".to_owned();
-    let mut method_url = None;
-    let mut url = "".to_owned();
-    let mut url_lvl = None;
-    let mut protocollvl = "".to_owned();
-    let mut lvl_statuscode = None;
-    let mut statuscode = "".to_owned();
-    let mut tail = "".to_owned();
-
-    // om nom nom
-    let mut chars = line.chars();
-    #[allow(clippy::never_loop)]
-    'outer: loop {
-        loop {
-            match chars.next() {
-                None => break 'outer,
-                Some('[') => break,
-                Some(chr) => head.push(chr),
-            }
+pub fn parse_nginx_line(line: &str) -> ParsedLine<'_> {
+    // Has to be able to parse a partial line: a field that isn't closed runs
+    // to the end of the line, the separators after it are None
+    let mut parsed = ParsedLine {
+        head: line,
+        head_date: None,
+        date: "",
+        date_method: None,
+        method: "",
+        method_url: None,
+        url: "",
+        url_lvl: None,
+        protocollvl: "",
+        lvl_statuscode: None,
+        statuscode: "",
+        tail: "",
+    };
+    // all separators are ascii, so the positions are on char boundaries
+    let find = |start: usize, separator: u8| -> Result<usize, &str> {
+        match line.as_bytes()[start..]
+            .iter()
+            .position(|x| *x == separator)
+        {
+            Some(x) => Ok(start + x),
+            None => Err(&line[start..]),
         }
-        head_date = Some("[".to_owned());
+    };
 
-        loop {
-            match chars.next() {
-                None => break 'outer,
-                Some(']') => break,
-                Some(chr) => date.push(chr),
+    'outer: {
+        let open = match find(0, b'[') {
+            Ok(x) => x,
+            Err(_) => break 'outer,
+        };
+        parsed.head = &line[..open];
+        parsed.head_date = Some(&line[open..=open]);
+
+        let close = match find(open + 1, b']') {
+            Ok(x) => x,
+            Err(rest) => {
+                parsed.date = rest;
+                break 'outer;
             }
-        }
-        date_method = Some("]".to_owned());
-        match chars.next() {
-            Some(' ') => date_method.as_mut().unwrap().push(' '),
-            Some(x) => {
-                tail.push(x);
+        };
+        parsed.date = &line[open + 1..close];
+        parsed.date_method = Some(&line[close..=close]);
+        match line.as_bytes().get(close + 1) {
+            Some(b' ') => {}
+            Some(_) => {
+                parsed.tail = &line[close + 1..];
                 break 'outer;
             }
             None => break 'outer,
         }
 
-        loop {
-            match chars.next() {
-                None => break 'outer,
-                Some('"') => break,
-                Some(chr) => date_method.as_mut().unwrap().push(chr),
+        let quote = match find(close + 2, b'"') {
+            Ok(x) => x,
+            Err(_) => {
+                parsed.date_method = Some(&line[close..]);
+                break 'outer;
             }
-        }
-        date_method.as_mut().unwrap().push('"');
+        };
+        parsed.date_method = Some(&line[close..=quote]);
 
-        loop {
-            match chars.next() {
-                None => break 'outer,
-                Some(' ') => break,
-                Some(chr) => method.push(chr),
+        let space = match find(quote + 1, b' ') {
+            Ok(x) => x,
+            Err(rest) => {
+                parsed.method = rest;
+                break 'outer;
             }
-        }
-        method_url = Some(" ".to_owned());
-        loop {
-            match chars.next() {
-                None => break 'outer,
-                Some(' ') => break,
-                Some(chr) => url.push(chr),
+        };
+        parsed.method = &line[quote + 1..space];
+        parsed.method_url = Some(&line[space..=space]);
+
+        let second_space = match find(space + 1, b' ') {
+            Ok(x) => x,
+            Err(rest) => {
+                parsed.url = rest;
+                break 'outer;
             }
-        }
-        url_lvl = Some(" ".to_owned());
-        loop {
-            match chars.next() {
-                None => break 'outer,
-                Some('"') => break,
-                Some(chr) => protocollvl.push(chr),
+        };
+        parsed.url = &line[space + 1..second_space];
+        parsed.url_lvl = Some(&line[second_space..=second_space]);
+
+        let second_quote = match find(second_space + 1, b'"') {
+            Ok(x) => x,
+            Err(rest) => {
+                parsed.protocollvl = rest;
+                break 'outer;
             }
-        }
-        lvl_statuscode = Some("\"".to_owned());
-        match chars.next() {
-            Some(' ') => lvl_statuscode.as_mut().unwrap().push(' '),
-            Some(x) => {
-                tail.push(x);
+        };
+        parsed.protocollvl = &line[second_space + 1..second_quote];
+        parsed.lvl_statuscode = Some(&line[second_quote..=second_quote]);
+        match line.as_bytes().get(second_quote + 1) {
+            Some(b' ') => parsed.lvl_statuscode = Some(&line[second_quote..second_quote + 2]),
+            Some(_) => {
+                parsed.tail = &line[second_quote + 1..];
                 break 'outer;
             }
             None => break 'outer,
         }
-        loop {
-            match chars.next() {
-                None => break 'outer,
-                Some(' ') => {
-                    tail.push(' ');
-                    break;
-                }
-                Some(chr) => statuscode.push(chr),
+
+        match find(second_quote + 2, b' ') {
+            Ok(end) => {
+                parsed.statuscode = &line[second_quote + 2..end];
+                parsed.tail = &line[end..];
             }
+            Err(rest) => parsed.statuscode = rest,
         }
-        break 'outer; // who said Rust didn't have goto ;-)
     }
-    tail.extend(chars);
-    ParsedLine {
-        head,
-        head_date,
+    parsed
+}
+
+/// A line from nginx' error log:
+/// 2024/01/01 12:00:00 [error] 123#0: *456 message, client: 1.2.3.4, server: x, request: "GET / HTTP/1.1"
+#[derive(PartialEq, Debug)]
+pub struct ErrorLine<'a> {
+    pub date: &'a str,                    // 2024/01/01 12:00:00
+    pub level: &'a str,                   // one of SEVERITIES
+    process: &'a str,                     // pid#tid
+    connection: Option<&'a str>,          // *456, only when there's a connection
+    pub message: &'a str,                 // up to the context
+    pub context: Vec<(&'a str, &'a str)>, // client: 1.2.3.4, request: "GET / HTTP/1.1"
+}
+
+/// nginx' error_log levels, least severe first
+pub const SEVERITIES: [&str; 8] = [
+    "debug", "info", "notice", "warn", "error", "crit", "alert", "emerg",
+];
+
+pub fn parse_error_line(line: &str) -> Option<ErrorLine<'_>> {
+    // "2024/01/01 12:00:00" is 19 bytes, all of them ascii
+    let date = line.get(..19)?;
+    let is_date = date.bytes().enumerate().all(|(index, x)| match index {
+        4 | 7 => x == b'/',
+        10 => x == b' ',
+        13 | 16 => x == b':',
+        _ => x.is_ascii_digit(),
+    });
+    if !is_date {
+        return None;
+    }
+    let (level, rest) = line[19..].strip_prefix(" [")?.split_once("] ")?;
+    if !SEVERITIES.contains(&level) {
+        return None;
+    }
+    let (process, rest) = rest.split_once(": ")?;
+    let (connection, rest) = match rest.split_once(' ') {
+        Some((id, after))
+            if id.len() > 1
+                && id.starts_with('*')
+                && id[1..].bytes().all(|x| x.is_ascii_digit()) =>
+        {
+            (Some(id), after)
+        }
+        _ => (None, rest),
+    };
+
+    // the message can contain ", " as well, the context starts at the client
+    let (message, mut context_text) = match rest.find(", client: ") {
+        Some(start) => (&rest[..start], &rest[start..]),
+        None => (rest, ""),
+    };
+    let mut context = vec![];
+    while let Some(pair) = context_text.strip_prefix(", ") {
+        let Some((key, value)) = pair.split_once(": ") else {
+            break;
+        };
+        let end = match value.strip_prefix('"') {
+            Some(quoted) => quoted.find('"').map(|x| x + 2),
+            None => Some(value.find(", ").unwrap_or(value.len())),
+        };
+        let Some(end) = end else {
+            break;
+        };
+        context.push((key, &value[..end]));
+        context_text = &value[end..];
+    }
+    if !context_text.is_empty() {
+        // not what we expected, keep it in the message so nothing gets lost
+        return Some(ErrorLine {
+            date,
+            level,
+            process,
+            connection,
+            message: rest,
+            context: vec![],
+        });
+    }
+    Some(ErrorLine {
         date,
-        date_method,
-        method,
-        method_url,
-        url,
-        url_lvl,
-        protocollvl,
-        lvl_statuscode,
-        statuscode,
-        tail,
+        level,
+        process,
+        connection,
+        message,
+        context,
+    })
+}
+
+impl Display for ErrorLine<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (dim, reset) = (colors::theme().dim, colors::RESET);
+        let (color, color_reset) = code2color(self.level);
+        write!(
+            f,
+            "{} [{color}{}{color_reset}] {}: ",
+            self.date, self.level, self.process
+        )?;
+        if let Some(connection) = self.connection {
+            write!(f, "{connection} ")?;
+        }
+        write!(f, "{color}{}{color_reset}", self.message)?;
+        for (key, value) in self.context.iter() {
+            write!(f, ", {dim}{key}:{reset} {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The line with colors, whatever kind of line it is
+pub fn highlight(line: &str, keys: &JsonKeys) -> String {
+    if json::is_json(line) {
+        return JsonLine(line, keys).to_string();
+    }
+    match parse_error_line(line) {
+        Some(error_line) => error_line.to_string(),
+        None => parse_nginx_line(line).to_string(),
     }
 }
 
@@ -140,17 +256,25 @@ type ColorStartEnd = (&'static str, &'static str);
 
 #[inline]
 pub fn code2color(code: &str) -> ColorStartEnd {
+    let theme = colors::theme();
+    match code {
+        // error log lines have a severity instead
+        "emerg" | "alert" | "crit" | "error" => return (theme.server_error, colors::RESET),
+        "warn" => return (theme.client_error, colors::RESET),
+        "debug" => return (theme.dim, colors::RESET),
+        _ => {}
+    }
     match code.chars().next() {
         None => ("", ""),
-        Some('2') => (colors::GREEN, colors::RESET),
-        Some('3') => (colors::PURPLE, colors::RESET),
-        Some('4') => (colors::YELLOW, colors::RESET),
-        Some('5') => (colors::RED, colors::RESET),
-        _ => (colors::WHITE, colors::RESET),
+        Some('2') => (theme.success, colors::RESET),
+        Some('3') => (theme.redirect, colors::RESET),
+        Some('4') => (theme.client_error, colors::RESET),
+        Some('5') => (theme.server_error, colors::RESET),
+        _ => (theme.other_status, colors::RESET),
     }
 }
 
-impl Display for ParsedLine {
+impl Display for ParsedLine<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Write all the bits separately, returning early when we run out of bits to print
         // self.tail will contain any unparsed text, it should always be printed.
@@ -171,8 +295,8 @@ impl Display for ParsedLine {
         write!(f, "{head_date}{}", &self.date)?;
 
         let date_method = unwrap_or_print_tail_then_return_ok!(&self.date_method);
-        let (color, reset) = match self.method.as_str() {
-            "POST" => (colors::REVERSE, colors::RESET),
+        let (color, reset) = match self.method {
+            "POST" => (colors::theme().highlight, colors::RESET),
             _ => ("", ""),
         };
         write!(f, "{date_method}{color}{}{reset}", &self.method)?;
@@ -185,7 +309,7 @@ impl Display for ParsedLine {
 
         let lvl_statuscode = unwrap_or_print_tail_then_return_ok!(&self.lvl_statuscode);
 
-        let (color, reset) = code2color(&self.statuscode);
+        let (color, reset) = code2color(self.statuscode);
         write!(
             f,
             "{lvl_statuscode}{color}{}{reset}{}",
@@ -196,18 +320,46 @@ impl Display for ParsedLine {
 
 #[cfg(test)]
 mod tests {
-    use crate::terminal::colors::{GREEN, RESET};
+    use crate::terminal::colors::{GREEN, RESET, WHITE};
+    use crate::terminal::{truncate_to_width, visible_width};
     use crate::{
-        extract_statuscode,
-        parsing::{ParsedLine, parse_nginx_line},
+        format::Format,
+        json::JsonKeys,
+        parsing::{ErrorLine, ParsedLine, highlight, parse_error_line, parse_nginx_line},
     };
 
     #[test]
     fn test_parsing() {
         let variant1 = r#"v2 1.22.3.44 - - [26/May/2025:00:00:01 +0200] "GET /v2/installations/74453/stats?interval=hours&type=evcs&start=1748210400 HTTP/1.0" 200 63 - 0.023 0.022 "-" "UserAgent/123" "https" "some.domain.example""#.to_owned();
-        assert_eq!("200", extract_statuscode(&variant1).unwrap());
+        let fields = |line| {
+            let fields = Format::CUSTOM.fields(line, &JsonKeys::default());
+            (fields.statuscode, fields.bytes, fields.request_time)
+        };
+        assert_eq!((Some("200"), Some(63), Some(0.023)), fields(&variant1));
         let variant2 = r#"123.123.123.123 - - [26/May/2025:19:43:59 +0200] "GET /links.json HTTP/1.1" 200 91 "-" "Monit/5.34.3" 0.004 0.004 ."#.to_owned();
-        assert_eq!("200", extract_statuscode(&variant2).unwrap());
+        assert_eq!((Some("200"), Some(91), Some(0.004)), fields(&variant2));
+        assert_eq!(None, fields(r#"[date] "GET / HTTP/1.1" 200 "#).1);
+        assert_eq!(
+            None,
+            fields(
+                r#"1.2.3.4 - - [date] "GET / HTTP/1.1" 200 612 "-" "Mozilla/5.0 Firefox/115.0""#
+            )
+            .2
+        );
+
+        let json = r#"{"request": "GET /links.json HTTP/1.1", "status": "404", "body_bytes_sent": 91, "request_time": "0.004"}"#;
+        let fields = Format::JSON.fields(json, &JsonKeys::default());
+        assert_eq!(
+            (Some("404"), Some(91), Some(0.004)),
+            (fields.statuscode, fields.bytes, fields.request_time)
+        );
+        assert_eq!(&json[fields.url.unwrap()], "/links.json");
+        assert_eq!(
+            None,
+            Format::JSON
+                .fields(r#"{"request": "GET / HTTP/1.1"}"#, &JsonKeys::default())
+                .statuscode
+        );
 
         // Deconstructing the struct because it looks nicer with assert_eq
         let ParsedLine {
@@ -237,6 +389,109 @@ mod tests {
         assert_eq!(statuscode, "200");
         assert_eq!(tail, r#" 91 "-" "Monit/5.34.3" 0.004 0.004 ."#);
     }
+    #[test]
+    fn test_partial_lines() {
+        let line =
+            r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /café HTTP/1.1" 200 12 "-" "curl/8""#;
+        for (index, _) in line.char_indices().chain([(line.len(), ' ')]) {
+            let partial = &line[..index];
+            let parsed = parse_nginx_line(partial);
+            let pieces = [
+                parsed.head,
+                parsed.head_date.unwrap_or(""),
+                parsed.date,
+                parsed.date_method.unwrap_or(""),
+                parsed.method,
+                parsed.method_url.unwrap_or(""),
+                parsed.url,
+                parsed.url_lvl.unwrap_or(""),
+                parsed.protocollvl,
+                parsed.lvl_statuscode.unwrap_or(""),
+                parsed.statuscode,
+                parsed.tail,
+            ];
+            assert_eq!(pieces.concat(), partial);
+        }
+        assert_eq!(parse_nginx_line(line).tail, r#" 12 "-" "curl/8""#);
+        assert_eq!(parse_nginx_line("no brackets").head, "no brackets");
+        assert_eq!(parse_nginx_line("[date]x").tail, "x");
+    }
+
+    #[test]
+    fn test_error_log() {
+        let line = r#"2024/01/01 12:00:00 [error] 123#0: *456 connect() failed (111: Connection refused) while connecting to upstream, client: 1.2.3.4, server: example.com, request: "GET /a, b HTTP/1.1", upstream: "http://127.0.0.1:8080/", host: "example.com""#;
+        assert_eq!(
+            parse_error_line(line),
+            Some(ErrorLine {
+                date: "2024/01/01 12:00:00",
+                level: "error",
+                process: "123#0",
+                connection: Some("*456"),
+                message: "connect() failed (111: Connection refused) while connecting to upstream",
+                context: vec![
+                    ("client", "1.2.3.4"),
+                    ("server", "example.com"),
+                    ("request", r#""GET /a, b HTTP/1.1""#),
+                    ("upstream", r#""http://127.0.0.1:8080/""#),
+                    ("host", r#""example.com""#),
+                ],
+            })
+        );
+
+        // no connection and no context
+        let line = "2024/01/01 12:00:00 [notice] 1#1: signal process started";
+        let parsed = parse_error_line(line).unwrap();
+        assert_eq!(
+            (parsed.connection, parsed.message),
+            (None, "signal process started")
+        );
+        assert_eq!(
+            highlight(line, &JsonKeys::default()),
+            format!(
+                "2024/01/01 12:00:00 [{WHITE}notice{RESET}] 1#1: {WHITE}signal process started{RESET}"
+            )
+        );
+
+        // an access log line isn't one
+        assert_eq!(
+            parse_error_line(r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET / HTTP/1.1" 200 1"#),
+            None
+        );
+        assert_eq!(parse_error_line("2024/01/01 12:00:00 [loud] 1#1: x"), None);
+    }
+
+    #[test]
+    fn test_truncating_unicode() {
+        let lines = [
+            r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /emoji/🎉🎉🎉/party HTTP/1.1" 200 12 "-" "Mozilla/5.0 (🦀 Ferris) 日本語ブラウザ" 0.004 0.004"#,
+            r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "POST /caf%C3%A9/café/naïve HTTP/1.1" 404 12 "-" "👍🏽 agent" 0.004 0.004"#,
+        ];
+        for line in lines {
+            let formatted = parse_nginx_line(line).to_string();
+            for width in 0..=visible_width(&formatted) + 1 {
+                let truncated = truncate_to_width(&formatted, width);
+                // at most one column short, when a wide character didn't fit
+                let expected = width.min(visible_width(&formatted));
+                assert!(visible_width(&truncated) <= expected, "{truncated:?}");
+                assert!(visible_width(&truncated) + 1 >= expected, "{truncated:?}");
+            }
+        }
+
+        let formatted = parse_nginx_line(lines[0]).to_string();
+        // the status code is cut in half, its color is still reset
+        assert_eq!(
+            truncate_to_width(&formatted, 77),
+            format!(
+                r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /emoji/🎉🎉🎉/party HTTP/1.1" {GREEN}2{RESET}"#
+            )
+        );
+        // a wide character that doesn't fit is left out entirely
+        assert_eq!(
+            truncate_to_width(&formatted, 55),
+            r#"1.2.3.4 - - [26/May/2025:00:00:01 +0200] "GET /emoji/🎉"#
+        );
+    }
+
     #[test]
     fn test_formatting_v3() {
         assert_eq!(
@@ -0,0 +1,357 @@
+// A small subset of regular expressions, just enough to rewrite URL paths
+// without pulling in a regex crate.
+//
+// Supported: literals, `.`, classes like `[a-z0-9_]` and `[^/]`, the shorthands
+// `\d \w \s` (and their uppercase negations), escaped characters, the greedy
+// quantifiers `* + ?` and the anchors `^ $`. Groups and alternation are not
+// supported.
+
+#[derive(Debug, PartialEq)]
+enum Atom {
+    Literal(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+}
+
+impl Atom {
+    fn matches(&self, chr: char) -> bool {
+        match self {
+            Atom::Literal(x) => *x == chr,
+            Atom::Any => true,
+            Atom::Class { ranges, negated } => {
+                ranges.iter().any(|(low, high)| *low <= chr && chr <= *high) != *negated
+            }
+        }
+    }
+
+    fn shorthand(chr: char) -> Option<Self> {
+        let (ranges, negated) = match chr {
+            'd' => (vec![('0', '9')], false),
+            'D' => (vec![('0', '9')], true),
+            'w' => (vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')], false),
+            'W' => (vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')], true),
+            's' => (
+                vec![(' ', ' '), ('\t', '\t'), ('\r', '\r'), ('\n', '\n')],
+                false,
+            ),
+            'S' => (
+                vec![(' ', ' '), ('\t', '\t'), ('\r', '\r'), ('\n', '\n')],
+                true,
+            ),
+            _ => return None,
+        };
+        Some(Atom::Class { ranges, negated })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Repeat {
+    Once,
+    Optional,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+#[derive(Debug, PartialEq)]
+struct Piece {
+    atom: Atom,
+    repeat: Repeat,
+}
+
+/// The text a pattern is looked for in, split once for all the matches in it
+struct Search {
+    chars: Vec<char>,
+    offsets: Vec<usize>, // byte offset of every char, and of the end of the text
+    // per piece and char: the rest of the pattern doesn't match from there,
+    // without this backtracking over fe. `a*a*a*b` takes exponential time.
+    // Empty when there's at most one repeat, that can't backtrack that much.
+    failed: Vec<bool>,
+}
+
+impl Search {
+    fn new(text: &str, pattern: &Pattern) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let offsets = text
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([text.len()])
+            .collect();
+        let repeats = pattern.pieces.iter().filter(|x| x.repeat != Repeat::Once);
+        let failed = match repeats.count() {
+            0 | 1 => vec![],
+            _ => vec![false; pattern.pieces.len() * (chars.len() + 1)],
+        };
+        Search {
+            chars,
+            offsets,
+            failed,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Pattern {
+    pieces: Vec<Piece>,
+    anchored_start: bool,
+    anchored_end: bool,
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, String> {
+        let mut pieces: Vec<Piece> = vec![];
+        let mut anchored_start = false;
+        let mut anchored_end = false;
+        let mut chars = source.chars().peekable();
+        let mut first = true;
+
+        while let Some(chr) = chars.next() {
+            let atom = match chr {
+                '^' if first => {
+                    anchored_start = true;
+                    first = false;
+                    continue;
+                }
+                '$' if chars.peek().is_none() => {
+                    anchored_end = true;
+                    continue;
+                }
+                '*' | '+' | '?' => {
+                    let Some(last) = pieces.last_mut() else {
+                        return Err(format!("Nothing to repeat before '{chr}' in {source:?}"));
+                    };
+                    if last.repeat != Repeat::Once {
+                        return Err(format!("Double repetition '{chr}' in {source:?}"));
+                    }
+                    last.repeat = match chr {
+                        '*' => Repeat::ZeroOrMore,
+                        '+' => Repeat::OneOrMore,
+                        _ => Repeat::Optional,
+                    };
+                    continue;
+                }
+                '(' | ')' | '|' | '{' | '}' => {
+                    return Err(format!("Unsupported pattern syntax '{chr}' in {source:?}"));
+                }
+                '.' => Atom::Any,
+                '\\' => match chars.next() {
+                    None => return Err(format!("Trailing backslash in {source:?}")),
+                    Some(escaped) => Atom::shorthand(escaped).unwrap_or(Atom::Literal(escaped)),
+                },
+                '[' => {
+                    let mut ranges = vec![];
+                    let negated = chars.next_if_eq(&'^').is_some();
+                    loop {
+                        let low = match chars.next() {
+                            None => return Err(format!("Unterminated [ in {source:?}")),
+                            Some(']') => break,
+                            Some('\\') => match chars.next() {
+                                None => return Err(format!("Trailing backslash in {source:?}")),
+                                Some(escaped) => {
+                                    if let Some(Atom::Class {
+                                        ranges: shorthand,
+                                        negated: false,
+                                    }) = Atom::shorthand(escaped)
+                                    {
+                                        ranges.extend(shorthand);
+                                        continue;
+                                    }
+                                    escaped
+                                }
+                            },
+                            Some(x) => x,
+                        };
+                        if chars.next_if_eq(&'-').is_some() {
+                            match chars.next() {
+                                None => return Err(format!("Unterminated [ in {source:?}")),
+                                Some(']') => {
+                                    // a trailing dash is just a dash
+                                    ranges.push((low, low));
+                                    ranges.push(('-', '-'));
+                                    break;
+                                }
+                                Some(high) if high < low => {
+                                    return Err(format!(
+                                        "Range {low}-{high} is out of order in {source:?}"
+                                    ));
+                                }
+                                Some(high) => ranges.push((low, high)),
+                            }
+                        } else {
+                            ranges.push((low, low));
+                        }
+                    }
+                    Atom::Class { ranges, negated }
+                }
+                x => Atom::Literal(x),
+            };
+            first = false;
+            pieces.push(Piece {
+                atom,
+                repeat: Repeat::Once,
+            });
+        }
+        Ok(Pattern {
+            pieces,
+            anchored_start,
+            anchored_end,
+        })
+    }
+
+    /// Returns the char range of the first (leftmost, greedy) match at or after char `from`
+    fn find_from(&self, search: &mut Search, from: usize) -> Option<(usize, usize)> {
+        let last_start = if self.anchored_start {
+            0
+        } else {
+            search.chars.len()
+        };
+        (from..=last_start)
+            .find_map(|start| self.match_here(search, 0, start).map(|end| (start, end)))
+    }
+
+    /// Returns the index of the char just after the match
+    fn match_here(&self, search: &mut Search, piece_index: usize, pos: usize) -> Option<usize> {
+        let Some(piece) = self.pieces.get(piece_index) else {
+            return if self.anchored_end && pos != search.chars.len() {
+                None
+            } else {
+                Some(pos)
+            };
+        };
+        let failed = piece_index * (search.chars.len() + 1) + pos;
+        if search.failed.get(failed) == Some(&true) {
+            return None;
+        }
+        let chars = &search.chars;
+        let matches_at = |x: usize| x < chars.len() && piece.atom.matches(chars[x]);
+        let end = match piece.repeat {
+            Repeat::Once => match matches_at(pos) {
+                true => self.match_here(search, piece_index + 1, pos + 1),
+                false => None,
+            },
+            Repeat::Optional => match matches_at(pos) {
+                true => self
+                    .match_here(search, piece_index + 1, pos + 1)
+                    .or_else(|| self.match_here(search, piece_index + 1, pos)),
+                false => self.match_here(search, piece_index + 1, pos),
+            },
+            Repeat::ZeroOrMore | Repeat::OneOrMore => {
+                let mut run = 0;
+                while matches_at(pos + run) {
+                    run += 1;
+                }
+                let minimum = if piece.repeat == Repeat::OneOrMore {
+                    1
+                } else {
+                    0
+                };
+                // greedy: try the longest run first
+                (minimum..=run)
+                    .rev()
+                    .find_map(|x| self.match_here(search, piece_index + 1, pos + x))
+            }
+        };
+        if end.is_none()
+            && let Some(failed) = search.failed.get_mut(failed)
+        {
+            *failed = true;
+        }
+        end
+    }
+
+    /// Replaces every non-overlapping match with `replacement`
+    pub fn replace_all(&self, text: &str, replacement: &str) -> String {
+        let mut search = Search::new(text, self);
+        let mut result = String::with_capacity(text.len());
+        let mut copied_until = 0; // bytes
+        let mut search_from = 0; // chars
+        while search_from <= search.chars.len() {
+            let Some((start, end)) = self.find_from(&mut search, search_from) else {
+                break;
+            };
+            result.push_str(&text[copied_until..search.offsets[start]]);
+            result.push_str(replacement);
+            copied_until = search.offsets[end];
+            search_from = if end > start {
+                end
+            } else {
+                // an empty match: step over one character to guarantee progress
+                match search.chars.get(end) {
+                    None => break,
+                    Some(x) => {
+                        result.push(*x);
+                        copied_until = search.offsets[end + 1];
+                        end + 1
+                    }
+                }
+            };
+            if self.anchored_start {
+                break;
+            }
+        }
+        result.push_str(&text[copied_until..]);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pattern, Search};
+
+    #[test]
+    fn test_find() {
+        let find = |pattern: &str, text: &str| {
+            let pattern = Pattern::new(pattern).unwrap();
+            let mut search = Search::new(text, &pattern);
+            let (start, end) = pattern.find_from(&mut search, 0)?;
+            Some((search.offsets[start], search.offsets[end]))
+        };
+        assert_eq!(find("abc", "xxabcxx"), Some((2, 5)));
+        assert_eq!(find("^abc", "xxabc"), None);
+        assert_eq!(find("abc$", "abcx"), None);
+        assert_eq!(find("a.c", "abc"), Some((0, 3)));
+        assert_eq!(find(r"\d+", "v12/345"), Some((1, 3)));
+        assert_eq!(find(r"[^/]+$", "/a/bcd"), Some((3, 6)));
+        assert_eq!(find("[a-c-]+", "xx-ab-c-d"), Some((2, 8)));
+        assert_eq!(find("colou?r", "color"), Some((0, 5)));
+        assert_eq!(find("/.*/", "/a/b/c"), Some((0, 5)));
+        assert_eq!(find(r"\.php$", "/index.php"), Some((6, 10)));
+        assert_eq!(find("é+", "caféé!"), Some((3, 7)));
+        assert_eq!(find("x*", "abc"), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let replace = |pattern: &str, text: &str, replacement: &str| {
+            Pattern::new(pattern)
+                .unwrap()
+                .replace_all(text, replacement)
+        };
+        assert_eq!(replace(r"\d+", "/a/12/b/3", ":n"), "/a/:n/b/:n");
+        assert_eq!(
+            replace("^/static/.*", "/static/js/app.js", "/static/*"),
+            "/static/*"
+        );
+        assert_eq!(replace("^/a", "/a/a", "/b"), "/b/a");
+        assert_eq!(replace("x*", "ab", "-"), "-a-b-");
+        assert_eq!(replace("zzz", "ab", "-"), "ab");
+        assert_eq!(replace("é?", "aéb", "-"), "-a--b-");
+
+        // every way to split the a's over the pieces fails, each is tried once
+        let many = "a".repeat(50);
+        assert_eq!(replace("a*a*a*a*a*a*a*a*a*a*b", &many, "-"), many);
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(Pattern::new("*a").is_err());
+        assert!(Pattern::new("a**").is_err());
+        assert!(Pattern::new("(a|b)").is_err());
+        assert!(Pattern::new("[abc").is_err());
+        assert!(Pattern::new(r"abc\").is_err());
+        assert!(Pattern::new("[z-a]").is_err());
+        assert!(Pattern::new("[a-a]").is_ok());
+    }
+}
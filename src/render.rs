@@ -0,0 +1,1050 @@
+// Pieces of the TUI that are pure text, kept apart from process_as_tui so
+// they can be tested without a terminal.
+
+use std::time::{Duration, SystemTime};
+
+use crate::collections::{
+    FileInfo, FocusStats, GroupView, LATENCY_BOUNDS, LATENCY_BUCKETS, LatencyColumn, StatusView,
+};
+use crate::dedup::with_count;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::filter::Filter;
+use crate::parsing::code2color;
+use crate::sampling::Strategy;
+use crate::speedometer::human_bytes_per_second;
+use crate::terminal::{colors, skip_width, truncate_to_width, visible_width};
+use crate::{FileState, GroupBy, Modes, get_statuscode_class};
+
+/// Every key handled by keyboard_reader, shown in the help overlay
+pub const KEYBINDINGS: &[(&str, &str)] = &[
+    ("q", "quit"),
+    ("s", "update stats now"),
+    ("l", "flush lines now"),
+    ("f", "edit the filter (enter to apply, esc to cancel)"),
+    ("c", "combine all groups into a single row"),
+    ("m", "merge status codes into 2xx, 3xx, ..."),
+    ("space", "pause/resume the lines, the stats keep running"),
+    ("up/down", "select a group"),
+    ("left/right", "scroll the lines sideways"),
+    (
+        "enter",
+        "focus on the selected group: only its lines and more details",
+    ),
+    ("esc", "back to the overview"),
+    ("b", "show the stats as bars instead of numbers"),
+    ("h", "latency heatmap of the selected group"),
+    ("u", "show repeated lines once with a count"),
+    ("d", "show/hide the warnings and errors"),
+    ("?", "show/hide this help"),
+];
+
+fn on_off(value: bool) -> &'static str {
+    match value {
+        true => "on",
+        false => "off",
+    }
+}
+
+/// One group in the stats table
+pub struct StatsRow<'a> {
+    pub tag: String, // padded to the same length for every row
+    pub groupview: &'a GroupView,
+    pub selected: bool,
+    pub stale: bool,
+    pub freshness: String,
+}
+impl StatsRow<'_> {
+    fn start(&self) -> String {
+        let grey = match self.stale {
+            true => colors::theme().dim,
+            false => "",
+        };
+        let (color, reset) = match self.selected {
+            true => (colors::theme().highlight, colors::RESET),
+            false => ("", ""),
+        };
+        format!("{grey}-- {color}{}{reset}{grey} ", self.tag)
+    }
+
+    fn end(&self) -> String {
+        let reset = match self.stale {
+            true => colors::RESET,
+            false => "",
+        };
+        format!("{}{reset}\n", self.freshness)
+    }
+}
+
+const OTHER: &str = "other";
+
+fn cell(statusview: &StatusView, stale: bool) -> String {
+    let (color, reset) = match stale {
+        true => ("", ""),
+        false => code2color(&statusview.statuscode),
+    };
+    format!(
+        "{:7.1} [{color}{}{reset}] {:>9} ",
+        statusview.speed,
+        statusview.statuscode,
+        human_bytes_per_second(statusview.bandwidth),
+    )
+}
+
+fn empty_cell(statuscode: &str) -> String {
+    #[cfg(debug_assertions)]
+    {
+        format!("{:>7}  {}  {:9} ", "", statuscode, "")
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        format!("{:7}  {}  {:9} ", "", " ".repeat(statuscode.len()), "")
+    }
+}
+
+fn cell_width(statuscode: &str) -> usize {
+    empty_cell(statuscode).chars().count()
+}
+
+/// Folds the status codes that are not in `kept` into a single "other"
+fn collapse(stats: &[StatusView], kept: &[String]) -> Vec<StatusView> {
+    let mut other = StatusView {
+        statuscode: OTHER.to_owned(),
+        speed: 0.0,
+        bandwidth: 0.0,
+    };
+    let mut collapsed = vec![];
+    for statusview in stats {
+        if kept.contains(&statusview.statuscode) {
+            collapsed.push(statusview.clone());
+        } else {
+            other.speed += statusview.speed;
+            other.bandwidth += statusview.bandwidth;
+        }
+    }
+    if collapsed.len() < stats.len() {
+        collapsed.push(other);
+    }
+    collapsed
+}
+
+/// The columns of a row: error logs count per severity instead of per status
+/// code, their rows get the severity columns so the access logs keep theirs
+fn row_columns(row: &StatsRow, statuscodes: &[String], severities: &[String]) -> Vec<String> {
+    let is_severity = |x: &StatusView| get_statuscode_class(&x.statuscode).is_none();
+    let stats = &row.groupview.stats;
+    match (stats.iter().any(is_severity), stats.iter().all(is_severity)) {
+        (true, true) => severities.to_vec(),
+        // fe. all groups combined
+        (true, false) => [statuscodes, severities].concat(),
+        (false, _) => statuscodes.to_vec(),
+    }
+}
+
+/// The stats of every group: a row per group and a column per status code.
+///
+/// When that's wider than `width` the least busy status codes are collapsed
+/// into "other", and when even a single column doesn't fit we switch to a
+/// line per status code. A width of 0 means there is no limit.
+pub fn stats_table(
+    rows: &[StatsRow],
+    statuscodes: &[String],
+    severities: &[String],
+    width: usize,
+) -> String {
+    let fixed_width = rows
+        .iter()
+        .map(|x| visible_width(&x.start()) + visible_width(&x.freshness))
+        .max()
+        .unwrap_or(0);
+    let fits = |statuscodes: &[String]| {
+        width == 0
+            || rows.iter().all(|row| {
+                let columns = row_columns(row, statuscodes, severities);
+                fixed_width + columns.iter().map(|x| cell_width(x)).sum::<usize>() <= width
+            })
+    };
+    if fits(statuscodes) {
+        return columns(rows, statuscodes, severities);
+    }
+
+    let total_speed = |statuscode: &String| -> f32 {
+        rows.iter()
+            .flat_map(|x| x.groupview.stats.iter())
+            .filter(|x| &x.statuscode == statuscode)
+            .map(|x| x.speed)
+            .sum()
+    };
+    let mut busiest = statuscodes.to_vec();
+    busiest.sort_by(|a, b| total_speed(b).total_cmp(&total_speed(a)));
+    for keep in (1..busiest.len()).rev() {
+        let mut kept = busiest[..keep].to_vec();
+        kept.sort();
+        kept.push(OTHER.to_owned());
+        if fits(&kept) {
+            return columns(rows, &kept, severities);
+        }
+    }
+
+    let mut table = "".to_owned();
+    for row in rows {
+        table += &row.start();
+        table.truncate(table.trim_end().len());
+        table += &row.end();
+        for statusview in row.groupview.stats.iter() {
+            table += &format!("   {}", cell(statusview, row.stale));
+            table.truncate(table.trim_end().len());
+            table += "\n";
+        }
+    }
+    table.truncate(table.trim_end().len());
+    table
+}
+
+/// The regular table, where "other" in `statuscodes` collects any status code
+/// that has no column of its own
+fn columns(rows: &[StatsRow], statuscodes: &[String], severities: &[String]) -> String {
+    let mut table = "".to_owned();
+    for row in rows {
+        table += &row.start();
+        let columns = row_columns(row, statuscodes, severities);
+        let stats = match columns.iter().any(|x| x == OTHER) {
+            true => collapse(&row.groupview.stats, &columns),
+            false => row.groupview.stats.clone(),
+        };
+        for statuscode in &columns {
+            match stats.iter().find(|x| &x.statuscode == statuscode) {
+                Some(statusview) => table += &cell(statusview, row.stale),
+                None => table += &empty_cell(statuscode),
+            }
+        }
+        table += &row.end();
+    }
+    table.truncate(table.trim_end().len());
+    table
+}
+
+/// Only the largest unit: 59s, 3m, 5h, 2d
+pub fn human_duration(duration: Duration) -> String {
+    match duration.as_secs() {
+        x if x < 60 => format!("{x}s"),
+        x if x < 60 * 60 => format!("{}m", x / 60),
+        x if x < 24 * 60 * 60 => format!("{}h", x / 60 / 60),
+        x => format!("{}d", x / 60 / 60 / 24),
+    }
+}
+
+/// How long a group has been silent plus anything unusual about its file,
+/// shown at the end of its stats row
+pub fn freshness(last_line: Option<Duration>, file: Option<&FileInfo>) -> String {
+    let text = match last_line {
+        None => " no lines yet".to_owned(),
+        Some(x) => format!(" last line {} ago", human_duration(x)),
+    };
+    match file {
+        None => text,
+        Some(file) => text + &file_details(file),
+    }
+}
+
+fn file_details(file: &FileInfo) -> String {
+    let mut text = "".to_owned();
+    if let Some(format) = file.format {
+        text += &format!(", {format} format");
+    }
+    if let Some(mtime) = file.mtime {
+        let age = SystemTime::now().duration_since(mtime).unwrap_or_default();
+        text += &format!(", modified {} ago", human_duration(age));
+    }
+    if let Some(rotated_at) = file.rotated_at
+        && rotated_at.elapsed() < Duration::from_secs(60)
+    {
+        text += &format!(" [rotated {} ago]", human_duration(rotated_at.elapsed()));
+    }
+    match &file.state {
+        FileState::Ok | FileState::Rotated => {}
+        FileState::Missing => text += " [missing]",
+        FileState::Unreadable(reason) => text += &format!(" [unreadable: {reason}]"),
+    }
+    text
+}
+
+/// Everything there is to know about the current session
+pub fn help(
+    modes: &Modes,
+    filter: &Filter,
+    group_by: GroupBy,
+    sampling: Strategy,
+    files: &[FileInfo],
+) -> String {
+    let mut help = "-- Keys\n".to_owned();
+    for (key, description) in KEYBINDINGS {
+        help += &format!("   [{key}] {description}\n");
+    }
+    let filter = match filter.is_empty() {
+        true => "none".to_owned(),
+        false => filter.to_string(),
+    };
+    help += &format!("-- Filter: {filter}\n");
+    // every mode, so a new one can't be left out
+    let Modes {
+        combine,
+        merge,
+        help: _, // on while this is shown
+        paused,
+        log,
+        bars,
+        heatmap,
+        dedup,
+    } = *modes;
+    let group_by = match group_by {
+        GroupBy::File => "file",
+        GroupBy::Route => "route",
+    };
+    help += &format!(
+        "-- Modes: group by {group_by}, sampling {}",
+        sampling.name()
+    );
+    for (name, on) in [
+        ("combine", combine),
+        ("merge", merge),
+        ("dedup", dedup),
+        ("bars", bars),
+        ("heatmap", heatmap),
+        ("paused", paused),
+        ("log", log),
+    ] {
+        help += &format!(", {name} {}", on_off(on));
+    }
+    help += "\n";
+    help += "-- Input:\n";
+    for file in files {
+        help += &format!("   {}{}\n", file.file, file_details(file));
+    }
+    help.truncate(help.trim_end().len());
+    help
+}
+
+/// The details of the focused group, shown instead of the stats
+pub fn focus(label: &str, groupview: Option<&GroupView>, focus: &FocusStats) -> String {
+    let mut text = format!("-- Focus on {label}   [esc] back to the overview\n");
+
+    text += "-- Status:";
+    for statusview in groupview.iter().flat_map(|x| x.stats.iter()) {
+        let (color, reset) = code2color(&statusview.statuscode);
+        text += &format!(
+            " {color}{}{reset} {:.1}/s {} |",
+            statusview.statuscode,
+            statusview.speed,
+            human_bytes_per_second(statusview.bandwidth)
+        );
+    }
+    text.truncate(text.trim_end_matches(" |").len());
+    text += "\n";
+
+    match focus.latency_percentiles(&[50, 90, 99, 100]) {
+        None => text += "-- Latency: no $request_time seen yet\n",
+        Some(x) => {
+            text += &format!(
+                "-- Latency of the last {} requests: p50 {:.3}s  p90 {:.3}s  p99 {:.3}s  max {:.3}s\n",
+                focus.latency_samples(),
+                x[0],
+                x[1],
+                x[2],
+                x[3]
+            )
+        }
+    }
+
+    text += &format!(
+        "-- Top routes of {} requests since focusing:",
+        focus.requests
+    );
+    for (route, count) in focus.top_routes(5) {
+        text += &format!("\n   {count:>7} {route}");
+    }
+    text
+}
+
+const CLASSES: [&str; 5] = ["2xx", "3xx", "4xx", "5xx", "other"];
+
+/// The color of a status class, or a distinct shade when there are no colors
+fn class_segment(class: &str, stale: bool) -> (&'static str, char, &'static str) {
+    let (color, reset) = match stale {
+        true => ("", ""),
+        false => code2color(class),
+    };
+    let shade = match (color.is_empty(), class) {
+        (false, _) => '█',
+        (true, "2xx") => '█',
+        (true, "3xx") => '▓',
+        (true, "4xx") => '▒',
+        (true, "5xx") => '░',
+        (true, _) => '·',
+    };
+    (color, shade, reset)
+}
+
+/// Splits `width` over the shares so the parts add up to exactly `width`,
+/// the largest remainders get the leftover columns
+fn apportion(shares: &[f32], width: usize) -> Vec<usize> {
+    let total: f32 = shares.iter().sum();
+    if total <= 0.0 {
+        return vec![0; shares.len()];
+    }
+    let exact: Vec<f32> = shares.iter().map(|x| x / total * width as f32).collect();
+    let mut parts: Vec<usize> = exact.iter().map(|x| *x as usize).collect();
+    let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+    by_remainder.sort_by(|a, b| {
+        (exact[*b] - exact[*b].floor()).total_cmp(&(exact[*a] - exact[*a].floor()))
+    });
+    let leftover = width.saturating_sub(parts.iter().sum());
+    for index in by_remainder.into_iter().take(leftover) {
+        parts[index] += 1;
+    }
+    parts
+}
+
+/// The share of every status class per group as a stacked bar, every bar
+/// spans the full width. A width of 0 means we don't know, 80 is used then.
+pub fn bar_chart(rows: &[StatsRow], width: usize) -> String {
+    const TOTAL_WIDTH: usize = 12; // "  1234.5/s "
+    let width = match width {
+        0 => 80,
+        x => x,
+    };
+    let mut chart = "".to_owned();
+    for row in rows {
+        let mut shares = [0.0; CLASSES.len()];
+        for statusview in row.groupview.stats.iter() {
+            let class = get_statuscode_class(&statusview.statuscode);
+            let index = CLASSES
+                .iter()
+                .position(|x| Some(*x) == class.as_deref())
+                .unwrap_or(CLASSES.len() - 1);
+            shares[index] += statusview.speed;
+        }
+        let start = row.start();
+        let bar_width = width
+            .saturating_sub(visible_width(&start) + TOTAL_WIDTH + visible_width(&row.freshness))
+            .max(10);
+
+        chart += &start;
+        for (class, part) in CLASSES.iter().zip(apportion(&shares, bar_width)) {
+            let (color, shade, reset) = class_segment(class, row.stale);
+            if part > 0 {
+                chart += &format!("{color}{}{reset}", shade.to_string().repeat(part));
+            }
+        }
+        if shares.iter().sum::<f32>() <= 0.0 {
+            chart += &" ".repeat(bar_width);
+        }
+        chart += &format!(" {:7.1}/s", shares.iter().sum::<f32>());
+        chart += &row.end();
+    }
+
+    chart += "-- ";
+    for class in CLASSES {
+        let (color, shade, reset) = class_segment(class, false);
+        chart += &format!("{color}{shade}{reset} {class}  ");
+    }
+    chart.truncate(chart.trim_end().len());
+    chart
+}
+
+/// A single warning or error, colored by its severity
+pub fn diagnostic(diagnostic: &Diagnostic) -> String {
+    let theme = colors::theme();
+    let (color, reset) = match diagnostic.severity {
+        Severity::Info => ("", ""),
+        Severity::Warning => (theme.client_error, colors::RESET),
+        Severity::Error => (theme.server_error, colors::RESET),
+    };
+    let text = diagnostic.to_string();
+    match text.split_once(": ") {
+        Some((severity, message)) => format!("{color}{severity}{reset}: {message}"),
+        None => text,
+    }
+}
+
+/// The most recent warnings and errors, shown instead of the stats
+pub fn log(log: &[Diagnostic]) -> String {
+    const SHOWN: usize = 10;
+    let mut text = format!(
+        "-- Messages ({} in total)   [d] back to the overview",
+        log.len()
+    );
+    if log.is_empty() {
+        text += "\n   nothing so far";
+    }
+    for item in log.iter().skip(log.len().saturating_sub(SHOWN)) {
+        text += &format!(
+            "\n   {:>3} ago {}",
+            human_duration(item.at.elapsed()),
+            diagnostic(item)
+        );
+    }
+    text
+}
+
+/// The lower bound of a latency bucket: 0ms, 1ms, ... 500ms, 1s, ... 10s
+fn latency_label(bucket: usize) -> String {
+    match bucket.checked_sub(1).map(|x| LATENCY_BOUNDS[x]) {
+        None => "0ms".to_owned(),
+        Some(x) if x < 1.0 => format!("{}ms", (x * 1000.0).round()),
+        Some(x) => format!("{x}s"),
+    }
+}
+
+/// Requests per $request_time bucket over time, slowest at the top and the
+/// most recent stats update on the right. Shown instead of the stats.
+pub fn heatmap(label: &str, columns: &[LatencyColumn], width: usize) -> String {
+    const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+    const LABEL_WIDTH: usize = 6;
+    let width = match width {
+        0 => 80,
+        x => x,
+    };
+    let shown = &columns[columns
+        .len()
+        .saturating_sub(width.saturating_sub(LABEL_WIDTH + 1))..];
+
+    let mut text = format!("-- Latency of {label}   [h] back to the overview");
+    let used: Vec<usize> = (0..LATENCY_BUCKETS)
+        .filter(|bucket| shown.iter().any(|x| x[*bucket] > 0))
+        .collect();
+    let (Some(fastest), Some(slowest)) = (used.first(), used.last()) else {
+        text += "\n   no $request_time seen yet";
+        return text;
+    };
+    let max = shown.iter().flatten().max().copied().unwrap_or(1);
+
+    // level 0 is an empty cell, the others are evenly spread up to max
+    let heat = colors::theme().heat;
+    let levels = match heat.is_empty() {
+        true => SHADES.len(),
+        false => heat.len() + 1,
+    };
+    let cell = |level: usize| match (heat.is_empty(), level) {
+        (true, _) => SHADES[level].to_string(),
+        (false, 0) => " ".to_owned(),
+        (false, _) => format!("{} {}", heat[level - 1], colors::RESET),
+    };
+    for bucket in (*fastest..=*slowest).rev() {
+        text += &format!("\n{:>LABEL_WIDTH$} ", latency_label(bucket));
+        for column in shown {
+            let level = (column[bucket] as usize * (levels - 1)).div_ceil(max as usize);
+            text += &cell(level);
+        }
+    }
+    text += &format!(
+        "\n{:LABEL_WIDTH$} {}{} up to {max} requests",
+        "",
+        cell(1),
+        cell(levels - 1)
+    );
+    text
+}
+
+/// A line above the stats, kept as it was highlighted so it can be drawn
+/// again when scrolling
+pub struct ShownLine {
+    pub text: String,
+    pub count: Option<u64>, // repeats, stays in view while scrolling
+    pub scrolls: bool,      // false for our own notes like "-- Output sampled"
+}
+impl ShownLine {
+    pub fn note(text: String) -> Self {
+        ShownLine {
+            text,
+            count: None,
+            scrolls: false,
+        }
+    }
+
+    /// Columns that can be scrolled through
+    pub fn width(&self) -> usize {
+        match self.scrolls {
+            true => visible_width(&self.text),
+            false => 0,
+        }
+    }
+
+    /// The line without its first `scroll` columns, cut to `width` unless that's 0
+    pub fn draw(&self, scroll: usize, width: usize) -> String {
+        if !self.scrolls {
+            return self.text.clone();
+        }
+        // cut after highlighting so the colors are never cut in half
+        let mut line = match scroll {
+            0 => self.text.clone(),
+            _ => skip_width(&self.text, scroll),
+        };
+        if let Some(count) = self.count {
+            line = with_count(&line, count);
+        }
+        match width {
+            0 => line,
+            _ => truncate_to_width(&line, width),
+        }
+    }
+}
+
+/// What the status bar reports besides the modes and the filter
+#[derive(Default)]
+pub struct Status {
+    pub samplerate: u32,
+    pub strategy: Strategy,
+    pub paused_lines: u64,
+    pub dropped_lines: u64, // counted in the stats, but the text never made it here
+    pub scroll: usize,      // columns hidden at the start of every line
+    pub messages: usize,    // in the log
+}
+
+/// One line summarizing the current mode, shown below the stats
+pub fn status_bar(modes: &Modes, filter: &Filter, status: &Status) -> String {
+    let Status {
+        samplerate,
+        strategy,
+        paused_lines,
+        dropped_lines,
+        scroll,
+        messages,
+    } = *status;
+    let (highlight, reset) = (colors::theme().highlight, colors::RESET);
+    let mut bar = match modes.paused {
+        true => format!("{highlight} PAUSED {reset} {paused_lines} new lines |"),
+        false => format!("{highlight} LIVE {reset}"),
+    };
+    if !filter.is_empty() {
+        bar += &format!(" filter: {filter} |");
+    }
+    bar += &match strategy {
+        Strategy::Newest => format!(" sampled at {samplerate}% |"),
+        _ => format!(" sampled at {samplerate}% ({}) |", strategy.name()),
+    };
+    if dropped_lines > 0 {
+        bar += &format!(" dropped {dropped_lines} lines |");
+    }
+    if scroll > 0 {
+        bar += &format!(" scrolled {scroll} columns |");
+    }
+    if modes.combine {
+        bar += " combined |";
+    }
+    if modes.merge {
+        bar += " merged |";
+    }
+    if modes.dedup {
+        bar += " dedup |";
+    }
+    if messages > 0 {
+        bar += &format!(" [d] {messages} messages |");
+    }
+    bar + " [?] help"
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime};
+
+    use super::{
+        ShownLine, StatsRow, Status, apportion, bar_chart, diagnostic, empty_cell, focus,
+        freshness, heatmap, help, human_duration, log, stats_table, status_bar,
+    };
+    use crate::collections::{FileInfo, FocusStats, GroupView, LATENCY_BUCKETS, StatusView};
+    use crate::diagnostics::{Diagnostic, Severity};
+    use crate::filter::Filter;
+    use crate::format::Format;
+    use crate::sampling::Strategy;
+    use crate::terminal::colors::{GREEN, RED, RESET, REVERSE};
+    use crate::{FileState, GroupBy, Modes};
+
+    fn statusview(statuscode: &str, speed: f32) -> StatusView {
+        StatusView {
+            statuscode: statuscode.to_owned(),
+            speed,
+            bandwidth: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_stats_table() {
+        let groupview = GroupView {
+            group: "site".to_owned(),
+            stats: vec![
+                statusview("200", 10.0),
+                statusview("301", 3.0),
+                statusview("404", 1.0),
+                statusview("502", 2.0),
+            ],
+            last_line: None,
+        };
+        let rows = [StatsRow {
+            tag: "site".to_owned(),
+            groupview: &groupview,
+            selected: false,
+            stale: true, // no colors
+            freshness: " x".to_owned(),
+        }];
+        let statuscodes: Vec<String> = ["200", "301", "404", "502"].map(String::from).into();
+        let grey = crate::terminal::colors::GREY;
+        let cells = |cells: &[(&str, &str)]| {
+            cells
+                .iter()
+                .map(|(speed, code)| format!("{speed:>7} [{code}]      0B/s "))
+                .collect::<String>()
+        };
+
+        let wide = format!(
+            "{grey}-- site{grey} {} x{RESET}",
+            cells(&[
+                ("10.0", "200"),
+                ("3.0", "301"),
+                ("1.0", "404"),
+                ("2.0", "502")
+            ])
+        );
+        assert_eq!(stats_table(&rows, &statuscodes, &[], 0), wide);
+        assert_eq!(stats_table(&rows, &statuscodes, &[], 106), wide);
+
+        // the least busy are collapsed first
+        assert_eq!(
+            stats_table(&rows, &statuscodes, &[], 105),
+            format!(
+                "{grey}-- site{grey} {} x{RESET}",
+                cells(&[("10.0", "200"), ("3.0", "301"), ("3.0", "other")])
+            )
+        );
+        assert_eq!(
+            stats_table(&rows, &statuscodes, &[], 60),
+            format!(
+                "{grey}-- site{grey} {} x{RESET}",
+                cells(&[("10.0", "200"), ("6.0", "other")])
+            )
+        );
+        assert_eq!(
+            stats_table(&rows, &statuscodes, &[], 59),
+            format!(
+                "{grey}-- site{grey} x{RESET}\n   {}\n   {}\n   {}\n   {}",
+                cells(&[("10.0", "200")]).trim_end(),
+                cells(&[("3.0", "301")]).trim_end(),
+                cells(&[("1.0", "404")]).trim_end(),
+                cells(&[("2.0", "502")]).trim_end(),
+            )
+        );
+    }
+
+    #[test]
+    fn test_stats_table_with_error_log() {
+        let access = GroupView {
+            group: "access".to_owned(),
+            stats: vec![statusview("200", 1.0)],
+            last_line: None,
+        };
+        let errors = GroupView {
+            group: "errors".to_owned(),
+            stats: vec![statusview("crit", 2.0)],
+            last_line: None,
+        };
+        fn row(groupview: &GroupView) -> StatsRow<'_> {
+            StatsRow {
+                tag: groupview.group.clone(),
+                groupview,
+                selected: false,
+                stale: true, // no colors
+                freshness: " x".to_owned(),
+            }
+        }
+        let rows = [row(&access), row(&errors)];
+        let statuscodes = ["200".to_owned()];
+        let severities = ["warn".to_owned(), "crit".to_owned()];
+        let grey = crate::terminal::colors::GREY;
+        let cell = |speed: &str, code: &str| format!("{speed:>7} [{code}]      0B/s ");
+
+        // the rows keep their own columns, without empty ones for the other kind
+        assert_eq!(
+            stats_table(&rows, &statuscodes, &severities, 0),
+            format!(
+                "{grey}-- access{grey} {} x{RESET}\n{grey}-- errors{grey} {}{} x{RESET}",
+                cell("1.0", "200"),
+                empty_cell("warn"),
+                cell("2.0", "crit"),
+            )
+        );
+    }
+
+    #[test]
+    fn test_bar_chart() {
+        assert_eq!(apportion(&[1.0, 1.0, 1.0], 10), vec![4, 3, 3]);
+        assert_eq!(apportion(&[0.0, 3.0, 1.0], 8), vec![0, 6, 2]);
+        assert_eq!(apportion(&[0.0, 0.0], 8), vec![0, 0]);
+
+        let groupview = GroupView {
+            group: "site".to_owned(),
+            stats: vec![
+                statusview("200", 5.0),
+                statusview("204", 1.0),
+                statusview("404", 2.0),
+                statusview("slow", 2.0),
+            ],
+            last_line: None,
+        };
+        let rows = [StatsRow {
+            tag: "site".to_owned(),
+            groupview: &groupview,
+            selected: false,
+            stale: true, // no colors, so shades
+            freshness: "".to_owned(),
+        }];
+        let grey = crate::terminal::colors::GREY;
+        let chart = bar_chart(&rows, 40);
+        let (bars, legend) = chart.split_once('\n').unwrap();
+        // 40 - "-- site " - " 10.0/s" leaves 20 columns: 60%, 20% and 20%
+        assert_eq!(
+            bars,
+            format!("{grey}-- site{grey} ████████████▒▒▒▒····    10.0/s{RESET}")
+        );
+        assert!(legend.starts_with(&format!("-- {GREEN}█{RESET} 2xx")));
+    }
+
+    #[test]
+    fn test_heatmap() {
+        assert_eq!(
+            heatmap("site", &[], 80),
+            "-- Latency of site   [h] back to the overview\n   no $request_time seen yet"
+        );
+
+        let mut fast = [0; LATENCY_BUCKETS];
+        fast[2] = 8; // 2ms..5ms
+        let mut bimodal = fast;
+        bimodal[2] = 4;
+        bimodal[10] = 1; // 1s..2s
+        let columns = [[0; LATENCY_BUCKETS], fast, bimodal];
+        // only the buckets in use, the oldest column doesn't fit
+        assert_eq!(
+            heatmap("site", &columns, 9),
+            [
+                "-- Latency of site   [h] back to the overview",
+                "    1s  ░",
+                " 500ms   ",
+                " 200ms   ",
+                " 100ms   ",
+                "  50ms   ",
+                "  20ms   ",
+                "  10ms   ",
+                "   5ms   ",
+                "   2ms █▒",
+                "       ░█ up to 8 requests",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_log() {
+        assert_eq!(
+            log(&[]),
+            "-- Messages (0 in total)   [d] back to the overview\n   nothing so far"
+        );
+        let diagnostics: Vec<Diagnostic> = (0..12)
+            .map(|x| Diagnostic::new(Severity::Info, format!("message {x}")))
+            .collect();
+        let text = log(&diagnostics);
+        assert_eq!(text.lines().count(), 11);
+        assert!(text.starts_with("-- Messages (12 in total)"));
+        assert!(text.ends_with("\n    0s ago info: message 11"));
+
+        let error = Diagnostic::new(Severity::Error, "access.log is not readable: denied");
+        assert_eq!(
+            diagnostic(&error),
+            format!("{RED}error{RESET}: access.log is not readable: denied")
+        );
+    }
+
+    #[test]
+    fn test_human_duration() {
+        assert_eq!(human_duration(Duration::from_millis(900)), "0s");
+        assert_eq!(human_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(human_duration(Duration::from_secs(60)), "1m");
+        assert_eq!(human_duration(Duration::from_secs(3 * 3600 + 10)), "3h");
+        assert_eq!(human_duration(Duration::from_secs(50 * 3600)), "2d");
+    }
+
+    #[test]
+    fn test_freshness() {
+        assert_eq!(freshness(None, None), " no lines yet");
+        assert_eq!(
+            freshness(Some(Duration::from_secs(5)), None),
+            " last line 5s ago"
+        );
+
+        let mut file = FileInfo::new("access.log".to_owned());
+        file.update(
+            FileState::Rotated,
+            Some(SystemTime::now() - Duration::from_secs(180)),
+        );
+        file.update(FileState::Ok, file.mtime);
+        assert_eq!(
+            freshness(Some(Duration::from_secs(5)), Some(&file)),
+            " last line 5s ago, modified 3m ago [rotated 0s ago]"
+        );
+
+        file.rotated_at = Some(Instant::now() - Duration::from_secs(61));
+        file.update(FileState::Unreadable("Permission denied".to_owned()), None);
+        assert_eq!(
+            freshness(None, Some(&file)),
+            " no lines yet [unreadable: Permission denied]"
+        );
+        file.update(FileState::Missing, None);
+        assert_eq!(freshness(None, Some(&file)), " no lines yet [missing]");
+        file.format = Some(Format::JSON);
+        assert_eq!(
+            freshness(None, Some(&file)),
+            " no lines yet, json format [missing]"
+        );
+    }
+
+    #[test]
+    fn test_shown_line() {
+        let line = ShownLine {
+            text: format!("{RED}GET{RESET} /abc/def"),
+            count: None,
+            scrolls: true,
+        };
+        assert_eq!(line.width(), 12);
+        assert_eq!(line.draw(0, 0), line.text);
+        assert_eq!(line.draw(0, 6), format!("{RED}GET{RESET} /a{RESET}"));
+        // the colors stay, only the text is scrolled away
+        assert_eq!(line.draw(4, 0), format!("{RED}{RESET}/abc/def"));
+        assert_eq!(line.draw(8, 3), format!("{RED}{RESET}/de{RESET}"));
+
+        // the count isn't scrolled away
+        let line = ShownLine {
+            count: Some(3),
+            ..line
+        };
+        assert_eq!(line.draw(9, 0), format!("      3 {RED}{RESET}def"));
+
+        // notes aren't scrolled or cut
+        let note = ShownLine::note("-- Output sampled at 50%".to_owned());
+        assert_eq!(note.width(), 0);
+        assert_eq!(note.draw(10, 5), "-- Output sampled at 50%");
+    }
+
+    #[test]
+    fn test_status_bar() {
+        let mut modes = Modes::default();
+        let status = Status {
+            samplerate: 100,
+            ..Status::default()
+        };
+        assert_eq!(
+            status_bar(&modes, &Filter::default(), &status),
+            format!("{REVERSE} LIVE {RESET} sampled at 100% | [?] help")
+        );
+        modes.merge = true;
+        let status = Status {
+            samplerate: 7,
+            strategy: Strategy::Stratified,
+            scroll: 40,
+            ..Status::default()
+        };
+        assert_eq!(
+            status_bar(&modes, &Filter::parse("5xx").unwrap(), &status),
+            format!(
+                "{REVERSE} LIVE {RESET} filter: 5xx | sampled at 7% (stratified) | scrolled 40 columns | merged | [?] help"
+            )
+        );
+        modes.paused = true;
+        let status = Status {
+            samplerate: 100,
+            paused_lines: 42,
+            dropped_lines: 1500,
+            messages: 3,
+            ..Status::default()
+        };
+        assert_eq!(
+            status_bar(&modes, &Filter::default(), &status),
+            format!(
+                "{REVERSE} PAUSED {RESET} 42 new lines | sampled at 100% | dropped 1500 lines | merged | [d] 3 messages | [?] help"
+            )
+        );
+    }
+
+    #[test]
+    fn test_focus() {
+        let mut stats = FocusStats::new("site".to_owned());
+        assert_eq!(
+            focus("site", None, &stats),
+            "-- Focus on site   [esc] back to the overview\n\
+             -- Status:\n\
+             -- Latency: no $request_time seen yet\n\
+             -- Top routes of 0 requests since focusing:"
+        );
+
+        stats.add(Some(0.5), Some("/a".to_owned()));
+        let groupview = GroupView {
+            group: "site".to_owned(),
+            stats: vec![
+                StatusView {
+                    statuscode: "200".to_owned(),
+                    speed: 1.0,
+                    bandwidth: 2000.0,
+                },
+                StatusView {
+                    statuscode: "502".to_owned(),
+                    speed: 0.5,
+                    bandwidth: 10.0,
+                },
+            ],
+            last_line: None,
+        };
+        assert_eq!(
+            focus("site", Some(&groupview), &stats),
+            format!(
+                "-- Focus on site   [esc] back to the overview\n\
+                 -- Status: {GREEN}200{RESET} 1.0/s 2.0KB/s | {RED}502{RESET} 0.5/s 10B/s\n\
+                 -- Latency of the last 1 requests: p50 0.500s  p90 0.500s  p99 0.500s  max 0.500s\n\
+                 -- Top routes of 1 requests since focusing:\n\
+                 \x20        1 /a"
+            )
+        );
+    }
+
+    #[test]
+    fn test_help() {
+        let text = help(
+            &Modes::default(),
+            &Filter::parse("404").unwrap(),
+            GroupBy::File,
+            Strategy::default(),
+            &[FileInfo::new("/var/log/nginx/access.log".to_owned())],
+        );
+        assert!(text.starts_with("-- Keys\n   [q] quit\n"));
+        assert!(text.contains("\n-- Filter: 404\n"));
+        assert!(text.contains(
+            "\n-- Modes: group by file, sampling newest, combine off, merge off, dedup off, bars off, heatmap off, paused off, log off\n"
+        ));
+        assert!(text.ends_with("-- Input:\n   /var/log/nginx/access.log"));
+
+        let modes = Modes {
+            combine: true,
+            paused: true,
+            bars: true,
+            heatmap: true,
+            dedup: true,
+            log: true,
+            ..Default::default()
+        };
+        let text = help(
+            &modes,
+            &Filter::default(),
+            GroupBy::Route,
+            Strategy::ErrorsFirst,
+            &[],
+        );
+        assert!(text.contains(
+            "\n-- Modes: group by route, sampling errors-first, combine on, merge off, dedup on, bars on, heatmap on, paused on, log on\n"
+        ));
+    }
+}
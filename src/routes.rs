@@ -0,0 +1,179 @@
+use crate::pattern::Pattern;
+
+/// Turns URLs into route templates so requests for different IDs end up in
+/// the same bucket: `/users/12345/orders?x=1` becomes `/users/:int/orders`.
+#[derive(Debug, Default)]
+pub struct RouteNormaliser {
+    rules: Vec<(Pattern, String)>,
+}
+
+impl RouteNormaliser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a user-supplied rewrite rule in the form `PATTERN=REPLACEMENT`.
+    /// It's split on the first `=`, so the replacement can have more of
+    /// them and the pattern has them escaped as `\=`. Rules are applied in
+    /// order, after the built-in normalisation.
+    pub fn add_rule(&mut self, rule: &str) -> Result<(), String> {
+        let Some(separator) = separator(rule) else {
+            return Err(format!(
+                "Rewrite rule {rule:?} should look like PATTERN=REPLACEMENT"
+            ));
+        };
+        let (pattern, replacement) = (&rule[..separator], &rule[separator + 1..]);
+        self.rules
+            .push((Pattern::new(pattern)?, replacement.to_owned()));
+        Ok(())
+    }
+
+    pub fn normalise(&self, url: &str) -> String {
+        // proxies can receive absolute URLs: http://example.com/path
+        let url = match url.split_once("://") {
+            Some((_, host_and_path)) => match host_and_path.find('/') {
+                Some(slash) => &host_and_path[slash..],
+                None => "/",
+            },
+            None => url,
+        };
+        let path = match url.find(['?', '#']) {
+            Some(end) => &url[..end],
+            None => url,
+        };
+
+        let mut route = path
+            .split('/')
+            .map(normalise_segment)
+            .collect::<Vec<_>>()
+            .join("/");
+        for (pattern, replacement) in self.rules.iter() {
+            route = pattern.replace_all(&route, replacement);
+        }
+        route
+    }
+}
+
+/// The position of the first `=` that isn't escaped with a backslash
+fn separator(rule: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (index, chr) in rule.char_indices() {
+        if chr == '=' && !escaped {
+            return Some(index);
+        }
+        escaped = chr == '\\' && !escaped;
+    }
+    None
+}
+
+fn normalise_segment(segment: &str) -> &str {
+    if segment.is_empty() {
+        segment
+    } else if segment.bytes().all(|x| x.is_ascii_digit()) {
+        ":int"
+    } else if is_uuid(segment) {
+        ":uuid"
+    } else if segment.len() >= 16
+        && segment.bytes().all(|x| x.is_ascii_hexdigit())
+        && segment.bytes().any(|x| x.is_ascii_digit())
+    {
+        // md5, sha1, object ids, ...
+        ":hex"
+    } else {
+        segment
+    }
+}
+
+fn is_uuid(segment: &str) -> bool {
+    segment.len() == 36
+        && segment.bytes().enumerate().all(|(index, x)| match index {
+            8 | 13 | 18 | 23 => x == b'-',
+            _ => x.is_ascii_hexdigit(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RouteNormaliser;
+
+    #[test]
+    fn test_builtin_normalisation() {
+        let normaliser = RouteNormaliser::new();
+        // A sample of paths seen in our own access logs plus some well known crawlers
+        let corpus = [
+            ("/", "/"),
+            ("/index.html", "/index.html"),
+            ("/links.json", "/links.json"),
+            (
+                "/users/12345/orders/9b2d5a1e-4f0c-4a8e-9a51-3c2f1d0e7b6a?x=1",
+                "/users/:int/orders/:uuid",
+            ),
+            (
+                "/v2/installations/74453/stats?interval=hours&type=evcs&start=1748210400",
+                "/v2/installations/:int/stats",
+            ),
+            ("/api/v1/items/42/", "/api/v1/items/:int/"),
+            (
+                "/static/js/app.3f9a2c1be4d07f56.js",
+                "/static/js/app.3f9a2c1be4d07f56.js",
+            ),
+            (
+                "/avatars/d41d8cd98f00b204e9800998ecf8427e.png",
+                "/avatars/d41d8cd98f00b204e9800998ecf8427e.png",
+            ),
+            (
+                "/commits/da39a3ee5e6b4b0d3255bfef95601890afd80709",
+                "/commits/:hex",
+            ),
+            (
+                "/objects/507f1f77bcf86cd799439011/edit",
+                "/objects/:hex/edit",
+            ),
+            (
+                "/blog/2024/01/my-first-post",
+                "/blog/:int/:int/my-first-post",
+            ),
+            ("/deadbeefdeadbeef", "/deadbeefdeadbeef"),
+            ("/search?q=nginx+tail", "/search"),
+            ("/docs/page#section-2", "/docs/page"),
+            ("/wp-login.php", "/wp-login.php"),
+            ("/.env", "/.env"),
+            (
+                "/cgi-bin/luci/;stok=/locale?form=country",
+                "/cgi-bin/luci/;stok=/locale",
+            ),
+            ("http://example.com/proxy/123", "/proxy/:int"),
+            ("http://example.com", "/"),
+            ("*", "*"),
+            ("/caf%C3%A9/12", "/caf%C3%A9/:int"),
+            ("/robots.txt", "/robots.txt"),
+            ("//double//slash/7", "//double//slash/:int"),
+        ];
+        for (url, expected) in corpus {
+            assert_eq!(normaliser.normalise(url), expected, "normalising {url}");
+        }
+    }
+
+    #[test]
+    fn test_rewrite_rules() {
+        let mut normaliser = RouteNormaliser::new();
+        normaliser.add_rule("^/static/.*=/static/*").unwrap();
+        normaliser.add_rule("^/users/[a-z]+=/users/:name").unwrap();
+        normaliser.add_rule(r"\.(php)=").unwrap_err();
+        assert!(normaliser.add_rule("no-equals-sign").is_err());
+        assert!(normaliser.add_rule(r"escaped\=equals-sign").is_err());
+        normaliser.add_rule("^/search$=/search?q=:term").unwrap();
+        normaliser.add_rule(r"^/key\=value$=/key=:value").unwrap();
+        normaliser.add_rule(r"^/back\\=/backslash").unwrap();
+
+        assert_eq!(normaliser.normalise("/static/css/a.css"), "/static/*");
+        assert_eq!(
+            normaliser.normalise("/users/alice/orders/1"),
+            "/users/:name/orders/:int"
+        );
+        assert_eq!(normaliser.normalise("/users/12"), "/users/:int");
+        assert_eq!(normaliser.normalise("/search?q=shoes"), "/search?q=:term");
+        assert_eq!(normaliser.normalise("/key=value"), "/key=:value");
+        assert_eq!(normaliser.normalise("/back\\"), "/backslash");
+    }
+}
@@ -0,0 +1,458 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::dedup::Repeated;
+use crate::get_statuscode_class;
+
+/// Which lines to show when more arrive between two updates than fit on the
+/// screen. The statistics are not affected.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    #[default]
+    Newest, // the most recent lines
+    Reservoir,   // a uniform random pick over the whole interval
+    Stratified,  // a random pick per status class, every class seen gets at least one line
+    ErrorsFirst, // the most recent 5xx, then 4xx, then everything else
+}
+impl Strategy {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "newest" => Ok(Strategy::Newest),
+            "reservoir" => Ok(Strategy::Reservoir),
+            "stratified" => Ok(Strategy::Stratified),
+            "errors-first" => Ok(Strategy::ErrorsFirst),
+            _ => Err(format!("Unknown sampling strategy {name:?}")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Newest => "newest",
+            Strategy::Reservoir => "reservoir",
+            Strategy::Stratified => "stratified",
+            Strategy::ErrorsFirst => "errors-first",
+        }
+    }
+
+    /// Whether the lines are kept per status class rather than all together
+    fn per_class(&self) -> bool {
+        matches!(self, Strategy::Stratified | Strategy::ErrorsFirst)
+    }
+}
+
+struct Kept {
+    seq: u64,            // to put the lines back in the order they arrived
+    key: Option<String>, // see dedup_key, lines with the same one are counted instead
+    line: Repeated,
+}
+
+/// Lines kept for one status class, or for all of them
+struct Bucket {
+    class: String,
+    seen: u64,
+    kept: VecDeque<Kept>,
+}
+
+/// How many lines of a status class were shown out of the ones that arrived
+#[derive(Debug, PartialEq)]
+pub struct ClassRate {
+    pub class: String,
+    pub shown: u64,
+    pub seen: u64,
+}
+
+/// What to show for one interval
+#[derive(Debug, Default)]
+pub struct Sample {
+    pub lines: Vec<Repeated>,    // in the order they arrived
+    pub classes: Vec<ClassRate>, // sorted by class
+}
+impl Sample {
+    /// Percentage of all lines that got shown
+    pub fn rate(&self) -> u32 {
+        let shown: u64 = self.classes.iter().map(|x| x.shown).sum();
+        let seen: u64 = self.classes.iter().map(|x| x.seen).sum();
+        match seen {
+            0 => 100,
+            _ => (100 * shown / seen) as u32,
+        }
+    }
+
+    /// "2xx: 3%, 5xx: 100%", empty when nothing was left out
+    pub fn rate_per_class(&self) -> String {
+        if self.classes.iter().all(|x| x.shown == x.seen) {
+            return "".to_owned();
+        }
+        let rates: Vec<String> = self
+            .classes
+            .iter()
+            .map(|x| format!("{}: {}%", x.class, 100 * x.shown / x.seen))
+            .collect();
+        rates.join(", ")
+    }
+}
+
+/// Collects the lines between two updates of the screen and picks the ones
+/// to show according to the Strategy
+pub struct Sampler {
+    pub strategy: Strategy,
+    seq: u64,
+    rng: u64,
+    seen: Vec<(String, u64)>, // per class, sorted
+    buckets: Vec<Bucket>,     // a single one unless the strategy works per class
+}
+
+impl Sampler {
+    pub fn new(strategy: Strategy) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |x| x.as_nanos() as u64);
+        Self::with_seed(strategy, seed)
+    }
+
+    fn with_seed(strategy: Strategy, seed: u64) -> Self {
+        Self {
+            strategy,
+            seq: 0,
+            rng: seed | 1, // xorshift gets stuck on 0
+            seen: vec![],
+            buckets: vec![],
+        }
+    }
+
+    /// xorshift64, good enough to pick log lines
+    fn random(&mut self, below: u64) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng % below
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Forgets everything since the last take
+    pub fn clear(&mut self) {
+        self.seen.clear();
+        self.buckets.clear();
+    }
+
+    /// `capacity` is the number of lines that fit on the screen. A line with
+    /// the same `key` as one that's kept only adds to its count.
+    pub fn add(
+        &mut self,
+        text: String,
+        statuscode: Option<Arc<str>>,
+        key: Option<String>,
+        capacity: usize,
+    ) {
+        let class = statuscode
+            .as_deref()
+            .and_then(get_statuscode_class)
+            .unwrap_or_else(|| "other".to_owned());
+        match self.seen.binary_search_by(|(x, _)| x.cmp(&class)) {
+            Ok(index) => self.seen[index].1 += 1,
+            Err(index) => self.seen.insert(index, (class.clone(), 1)),
+        }
+
+        let class = match self.strategy.per_class() {
+            true => class,
+            false => "".to_owned(),
+        };
+        let index = match self.buckets.iter().position(|x| x.class == class) {
+            Some(index) => index,
+            None => {
+                self.buckets.push(Bucket {
+                    class,
+                    seen: 0,
+                    kept: VecDeque::new(),
+                });
+                self.buckets.len() - 1
+            }
+        };
+        self.buckets[index].seen += 1;
+        if let Some(key) = &key
+            && let Some(kept) = self.buckets[index]
+                .kept
+                .iter_mut()
+                .find(|x| x.key.as_ref() == Some(key))
+        {
+            kept.line.count += 1;
+            return;
+        }
+        self.seq += 1;
+        let line = Kept {
+            seq: self.seq,
+            key,
+            line: Repeated {
+                text,
+                statuscode,
+                count: 1,
+            },
+        };
+
+        let capacity = capacity.max(1);
+        match self.strategy {
+            Strategy::Newest | Strategy::ErrorsFirst => {
+                let kept = &mut self.buckets[index].kept;
+                while kept.len() >= capacity {
+                    kept.pop_front();
+                }
+                kept.push_back(line);
+            }
+            Strategy::Reservoir | Strategy::Stratified => {
+                // Algorithm R: the n-th line replaces a random kept one with
+                // a chance of capacity/n
+                let seen = self.buckets[index].seen;
+                let slot = self.random(seen) as usize;
+                let kept = &mut self.buckets[index].kept;
+                if kept.len() < capacity {
+                    kept.push_back(line);
+                } else if slot < kept.len() {
+                    kept[slot] = line;
+                }
+            }
+        }
+    }
+
+    /// The lines to show, at most `capacity` of them, and starts a new interval
+    pub fn take(&mut self, capacity: usize) -> Sample {
+        let capacity = capacity.max(1);
+        let mut buckets = std::mem::take(&mut self.buckets);
+        let mut picked: Vec<Kept> = vec![];
+        match self.strategy {
+            Strategy::Newest | Strategy::Reservoir => {
+                for bucket in buckets.iter_mut() {
+                    let skip = bucket.kept.len().saturating_sub(capacity);
+                    picked.extend(bucket.kept.drain(..).skip(skip));
+                }
+            }
+            Strategy::Stratified => {
+                let counts = allocate(&buckets, capacity);
+                for (bucket, count) in buckets.iter_mut().zip(counts) {
+                    // a random subset: the kept lines are a uniform sample,
+                    // but not in a random order
+                    for index in 0..count {
+                        let other =
+                            index + self.random((bucket.kept.len() - index) as u64) as usize;
+                        bucket.kept.swap(index, other);
+                    }
+                    picked.extend(bucket.kept.drain(..count));
+                }
+            }
+            Strategy::ErrorsFirst => {
+                buckets.sort_by_key(|x| match x.class.as_str() {
+                    "5xx" => 0,
+                    "4xx" => 1,
+                    _ => 2,
+                });
+                let mut rest: Vec<Kept> = vec![];
+                for bucket in buckets.iter_mut() {
+                    match bucket.class.as_str() {
+                        "5xx" | "4xx" => {
+                            let count = bucket.kept.len().min(capacity - picked.len());
+                            let skip = bucket.kept.len() - count;
+                            picked.extend(bucket.kept.drain(..).skip(skip));
+                        }
+                        _ => rest.extend(bucket.kept.drain(..)),
+                    }
+                }
+                // everything else, the most recent first
+                rest.sort_by_key(|x| x.seq);
+                let skip = rest.len().saturating_sub(capacity - picked.len());
+                picked.extend(rest.into_iter().skip(skip));
+            }
+        }
+        picked.sort_by_key(|x| x.seq);
+
+        let mut classes: Vec<ClassRate> = std::mem::take(&mut self.seen)
+            .into_iter()
+            .map(|(class, seen)| ClassRate {
+                class,
+                shown: 0,
+                seen,
+            })
+            .collect();
+        let mut lines = Vec::with_capacity(picked.len());
+        for kept in picked {
+            let class = kept
+                .line
+                .statuscode
+                .as_deref()
+                .and_then(get_statuscode_class)
+                .unwrap_or_else(|| "other".to_owned());
+            if let Some(rate) = classes.iter_mut().find(|x| x.class == class) {
+                rate.shown += kept.line.count;
+            }
+            lines.push(kept.line);
+        }
+        Sample { lines, classes }
+    }
+}
+
+/// Lines per bucket for the stratified strategy: one for every bucket, the
+/// rarest first when there's not even room for that, and the rest in
+/// proportion to the number of lines seen
+fn allocate(buckets: &[Bucket], capacity: usize) -> Vec<usize> {
+    let mut counts = vec![0; buckets.len()];
+    let mut by_rarity: Vec<usize> = (0..buckets.len()).collect();
+    by_rarity.sort_by_key(|x| buckets[*x].seen);
+    for index in by_rarity.iter().take(capacity) {
+        counts[*index] = 1;
+    }
+
+    let mut left = capacity.saturating_sub(buckets.len());
+    let total: u64 = buckets.iter().map(|x| x.seen).sum();
+    let mut remainders: Vec<(usize, u64)> = vec![];
+    for (index, bucket) in buckets.iter().enumerate() {
+        let share = left as u64 * bucket.seen;
+        let extra = ((share / total) as usize).min(bucket.kept.len() - counts[index]);
+        counts[index] += extra;
+        remainders.push((index, share % total));
+    }
+    left = capacity.saturating_sub(counts.iter().sum());
+    // the lines left after rounding down, largest remainder first
+    remainders.sort_by_key(|x| cmp::Reverse(x.1));
+    for (index, _) in remainders {
+        if left == 0 {
+            break;
+        }
+        if counts[index] < buckets[index].kept.len() {
+            counts[index] += 1;
+            left -= 1;
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sampler, Strategy};
+    use crate::dedup::Repeated;
+
+    fn feed(sampler: &mut Sampler, lines: &[(&str, u32)], capacity: usize) {
+        for (statuscode, count) in lines {
+            for i in 0..*count {
+                let text = format!("{statuscode} #{i}");
+                sampler.add(text, Some((*statuscode).into()), None, capacity);
+            }
+        }
+    }
+
+    fn texts(lines: &[Repeated]) -> Vec<&str> {
+        lines.iter().map(|x| x.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_newest() {
+        let mut sampler = Sampler::with_seed(Strategy::Newest, 1);
+        feed(&mut sampler, &[("200", 10), ("500", 1), ("200", 2)], 3);
+        let sample = sampler.take(3);
+        assert_eq!(texts(&sample.lines), vec!["500 #0", "200 #0", "200 #1"]);
+        assert_eq!(sample.rate(), 23);
+        assert_eq!(sample.rate_per_class(), "2xx: 16%, 5xx: 100%");
+        assert!(sampler.is_empty());
+
+        feed(&mut sampler, &[("200", 2)], 3);
+        let sample = sampler.take(3);
+        assert_eq!(sample.rate(), 100);
+        assert_eq!(sample.rate_per_class(), "");
+    }
+
+    #[test]
+    fn test_reservoir() {
+        // every line has the same chance, not only the newest ones
+        let mut first_half = 0;
+        for seed in 1..=200 {
+            let mut sampler = Sampler::with_seed(Strategy::Reservoir, seed);
+            feed(&mut sampler, &[("200", 100)], 10);
+            let sample = sampler.take(10);
+            assert_eq!(sample.lines.len(), 10);
+            first_half += sample
+                .lines
+                .iter()
+                .filter(|x| x.text[5..].parse::<u32>().unwrap() < 50)
+                .count();
+        }
+        assert!((800..1200).contains(&first_half), "{first_half}");
+    }
+
+    #[test]
+    fn test_stratified() {
+        let mut sampler = Sampler::with_seed(Strategy::Stratified, 1);
+        feed(&mut sampler, &[("200", 1000), ("404", 30), ("502", 1)], 10);
+        let sample = sampler.take(10);
+        let classes: Vec<(&str, u64)> = sample
+            .classes
+            .iter()
+            .map(|x| (x.class.as_str(), x.shown))
+            .collect();
+        assert_eq!(classes, vec![("2xx", 8), ("4xx", 1), ("5xx", 1)]);
+        assert_eq!(sample.lines.len(), 10);
+
+        // less room than classes: the rarest win
+        feed(&mut sampler, &[("200", 1000), ("404", 30), ("502", 1)], 2);
+        let sample = sampler.take(2);
+        assert_eq!(texts(&sample.lines)[1], "502 #0");
+        assert!(texts(&sample.lines)[0].starts_with("404"));
+    }
+
+    #[test]
+    fn test_errors_first() {
+        let mut sampler = Sampler::with_seed(Strategy::ErrorsFirst, 1);
+        feed(
+            &mut sampler,
+            &[("404", 2), ("500", 2), ("200", 50), ("502", 1), ("200", 1)],
+            4,
+        );
+        sampler.add("garbage".to_owned(), None, None, 4);
+        let sample = sampler.take(4);
+        assert_eq!(
+            texts(&sample.lines),
+            vec!["404 #1", "500 #0", "500 #1", "502 #0"]
+        );
+
+        // room to spare goes to the most recent other lines
+        feed(&mut sampler, &[("500", 1), ("200", 5)], 4);
+        sampler.add("garbage".to_owned(), None, None, 4);
+        let sample = sampler.take(4);
+        assert_eq!(
+            texts(&sample.lines),
+            vec!["500 #0", "200 #3", "200 #4", "garbage"]
+        );
+    }
+
+    #[test]
+    fn test_dedup() {
+        let mut sampler = Sampler::with_seed(Strategy::Newest, 1);
+        for i in 0..50 {
+            let key = Some(format!("key {}", i % 2));
+            sampler.add(format!("line {i}"), Some("200".into()), key, 3);
+        }
+        sampler.add("other".to_owned(), Some("200".into()), None, 3);
+        let sample = sampler.take(3);
+        let lines: Vec<(&str, u64)> = sample
+            .lines
+            .iter()
+            .map(|x| (x.text.as_str(), x.count))
+            .collect();
+        assert_eq!(lines, vec![("line 0", 25), ("line 1", 25), ("other", 1)]);
+        assert_eq!(sample.rate(), 100);
+    }
+
+    #[test]
+    fn test_parse() {
+        for strategy in [
+            Strategy::Newest,
+            Strategy::Reservoir,
+            Strategy::Stratified,
+            Strategy::ErrorsFirst,
+        ] {
+            assert_eq!(Strategy::parse(strategy.name()), Ok(strategy));
+        }
+        assert!(Strategy::parse("random").is_err());
+    }
+}
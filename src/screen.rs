@@ -0,0 +1,185 @@
+// A copy of what's on the screen for the fullscreen mode, so drawing a new
+// frame only writes the cells that changed since the previous one.
+
+use crate::terminal::colors::{CSI, RESET};
+use crate::terminal::{char_width, copy_escape_sequence};
+
+#[derive(Debug, PartialEq, Clone)]
+struct Cell {
+    text: String,  // a character plus its combining marks, empty right of a wide character
+    style: String, // the escape sequences in effect for this cell
+}
+impl Cell {
+    fn blank() -> Self {
+        Self {
+            text: " ".to_owned(),
+            style: "".to_owned(),
+        }
+    }
+}
+
+pub struct Screen {
+    width: usize,
+    height: usize,
+    cells: Vec<Vec<Cell>>, // empty when we don't know what's on the screen
+}
+
+impl Screen {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width: width.into(),
+            height: height.into(),
+            cells: vec![],
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// When the size changed, the next frame will clear the screen and redraw everything
+    pub fn resize(&mut self, width: u16, height: u16) {
+        let (width, height) = (width.into(), height.into());
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.cells.clear();
+        }
+    }
+
+    /// Returns what to write to the terminal to go from the previous frame
+    /// to `rows`. Rows that are too wide are cut, missing rows are blank.
+    pub fn draw(&mut self, rows: &[String]) -> String {
+        let mut output = "".to_owned();
+        if self.cells.is_empty() {
+            output += &format!("{RESET}{CSI}2J");
+            self.cells = vec![vec![Cell::blank(); self.width]; self.height];
+        }
+
+        let mut cursor = None; // (row, column) where the next character ends up
+        let mut style: Option<String> = None; // the style of the terminal, unknown at first
+        for (y, previous) in self.cells.iter_mut().enumerate() {
+            let row = parse_row(rows.get(y).map_or("", |x| x.as_str()), self.width);
+            for (x, cell) in row.iter().enumerate() {
+                // the right half of a wide character is written along with the left half
+                if *cell == previous[x] || cell.text.is_empty() {
+                    continue;
+                }
+                if cursor != Some((y, x)) {
+                    output += &format!("{CSI}{};{}H", y + 1, x + 1);
+                }
+                if style.as_ref() != Some(&cell.style) {
+                    output += RESET;
+                    output += &cell.style;
+                    style = Some(cell.style.clone());
+                }
+                output += &cell.text;
+                let cell_width = match row.get(x + 1) {
+                    Some(next) if next.text.is_empty() => 2,
+                    _ => 1,
+                };
+                cursor = Some((y, x + cell_width));
+            }
+            *previous = row;
+        }
+        if style.is_some() {
+            output += RESET;
+        }
+        output
+    }
+}
+
+/// Splits a line with escape sequences into exactly `width` cells
+fn parse_row(row: &str, width: usize) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = Vec::with_capacity(width);
+    let mut style = "".to_owned();
+    let mut chars = row.chars();
+    while let Some(chr) = chars.next() {
+        if chr == '\x1b' {
+            let mut sequence = "".to_owned();
+            copy_escape_sequence(&mut chars, &mut sequence);
+            match sequence.as_str() {
+                RESET => style.clear(),
+                _ => style += &sequence,
+            }
+            continue;
+        }
+        match char_width(chr) {
+            0 => {
+                // combining marks belong to the character before them
+                if let Some(cell) = cells.iter_mut().rev().find(|x| !x.text.is_empty()) {
+                    cell.text.push(chr);
+                }
+            }
+            chr_width => {
+                if cells.len() + chr_width > width {
+                    break;
+                }
+                cells.push(Cell {
+                    text: chr.to_string(),
+                    style: style.clone(),
+                });
+                if chr_width == 2 {
+                    cells.push(Cell {
+                        text: "".to_owned(),
+                        style: style.clone(),
+                    });
+                }
+            }
+        }
+    }
+    cells.resize(width, Cell::blank());
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Screen, parse_row};
+    use crate::terminal::colors::{CSI, GREEN, RESET};
+
+    #[test]
+    fn test_parse_row() {
+        let row = parse_row(&format!("a{GREEN}日{RESET}b"), 6);
+        let text: Vec<&str> = row.iter().map(|x| x.text.as_str()).collect();
+        assert_eq!(text, vec!["a", "日", "", "b", " ", " "]);
+        assert_eq!(row[1].style, GREEN);
+        assert_eq!(row[3].style, "");
+
+        // cut, but never in the middle of a wide character
+        let row = parse_row("ab日", 3);
+        let text: Vec<&str> = row.iter().map(|x| x.text.as_str()).collect();
+        assert_eq!(text, vec!["a", "b", " "]);
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut screen = Screen::new(5, 2);
+        let rows = ["abc".to_owned(), format!("{GREEN}ok{RESET}")];
+        assert_eq!(
+            screen.draw(&rows),
+            format!("{RESET}{CSI}2J{CSI}1;1H{RESET}abc{CSI}2;1H{RESET}{GREEN}ok{RESET}")
+        );
+        // nothing changed, nothing to write
+        assert_eq!(screen.draw(&rows), "");
+
+        // only the changed cell is written
+        let rows = ["abd".to_owned(), format!("{GREEN}ok{RESET}")];
+        assert_eq!(screen.draw(&rows), format!("{CSI}1;3H{RESET}d{RESET}"));
+
+        // a shorter row blanks what's left of the longer one
+        assert_eq!(
+            screen.draw(&["a".to_owned()]),
+            format!("{CSI}1;2H{RESET}  {CSI}2;1H  {RESET}")
+        );
+
+        // the same size keeps what's on the screen
+        screen.resize(5, 2);
+        assert_eq!(screen.draw(&["a".to_owned()]), "");
+
+        screen.resize(3, 1);
+        assert_eq!(
+            screen.draw(&["abcdef".to_owned()]),
+            format!("{RESET}{CSI}2J{CSI}1;1H{RESET}abc{RESET}")
+        );
+    }
+}
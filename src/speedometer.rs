@@ -40,47 +40,84 @@ impl Speedometer for InstantSpeedometer {
     }
 }
 
-struct RingbufferMeasurement {
+struct RingbufferMeasurement<T> {
     duration: u32,
-    msgs: u32,
+    amount: T,
 }
-pub struct RingbufferSpeedometer {
-    measurements: VecDeque<RingbufferMeasurement>,
+/// The speed over the last `capacity` measurements. `T` is what gets counted:
+/// messages for the Speedometer, or a weight like the number of bytes sent.
+pub struct RingbufferSpeedometer<T = u32> {
+    measurements: VecDeque<RingbufferMeasurement<T>>,
+    capacity: usize,
 }
-impl Default for RingbufferSpeedometer {
+impl<T: Copy + Into<u64>> Default for RingbufferSpeedometer<T> {
     fn default() -> Self {
         Self::new(1024)
     }
 }
-impl RingbufferSpeedometer {
+impl<T: Copy + Into<u64>> RingbufferSpeedometer<T> {
     pub fn new(capacity: usize) -> Self {
         assert!(capacity > 0, "Capacity must be greater than 0");
-        let ring = VecDeque::with_capacity(capacity);
-        assert!(ring.capacity() == capacity, "This is unexpected");
-        Self { measurements: ring }
+        Self {
+            measurements: VecDeque::with_capacity(capacity),
+            capacity,
+        }
     }
-}
-impl Speedometer for RingbufferSpeedometer {
-    fn get_speed(&self) -> f32 {
+
+    /// The duration is in milliseconds, the amount is what was counted in that duration.
+    pub fn add(&mut self, duration: u32, amount: T) {
+        if self.measurements.len() == self.capacity {
+            let _ = self.measurements.pop_front();
+        }
+        self.measurements
+            .push_back(RingbufferMeasurement { duration, amount });
+    }
+
+    /// What was counted per second
+    pub fn per_second(&self) -> f32 {
         if self.measurements.is_empty() {
             return 0.0;
         }
-        let (time, msgs) = self
+        let (time, amount) = self
             .measurements
             .iter()
-            .fold((0_u32, 0_u32), |state, elem| {
-                (state.0 + elem.duration, state.1 + elem.msgs)
+            .fold((0_u64, 0_u64), |state, elem| {
+                (
+                    state.0 + u64::from(elem.duration),
+                    state.1 + elem.amount.into(),
+                )
             });
-        msgs as f32 * 1000.0 / (time as f32)
+        (amount as f64 * 1000.0 / time as f64) as f32
+    }
+}
+impl Speedometer for RingbufferSpeedometer {
+    fn get_speed(&self) -> f32 {
+        self.per_second()
     }
 
     fn add_measurement(&mut self, duration: u32, msgs: u32) {
-        if self.measurements.len() == self.measurements.capacity() {
-            let _ = self.measurements.pop_front();
+        self.add(duration, msgs);
+    }
+}
+
+/// A RingbufferSpeedometer for weights, fe. the number of bytes sent. It's no
+/// Speedometer, as weights aren't messages.
+pub type WeightedSpeedometer = RingbufferSpeedometer<u64>;
+
+/// Formats a speed in bytes per second as 512B/s, 1.5KB/s, 12.0MB/s, ...
+pub fn human_bytes_per_second(speed: f32) -> String {
+    const UNITS: [&str; 4] = ["KB/s", "MB/s", "GB/s", "TB/s"];
+    if speed < 1000.0 {
+        return format!("{speed:.0}B/s");
+    }
+    let mut speed = speed / 1000.0;
+    for unit in UNITS.iter().take(UNITS.len() - 1) {
+        if speed < 1000.0 {
+            return format!("{speed:.1}{unit}");
         }
-        self.measurements
-            .push_back(RingbufferMeasurement { duration, msgs });
+        speed /= 1000.0;
     }
+    format!("{speed:.1}{}", UNITS[UNITS.len() - 1])
 }
 
 pub struct SmootherSpeedometer {
@@ -108,13 +145,9 @@ impl Speedometer for SmootherSpeedometer {
     fn add_measurement(&mut self, duration: u32, msgs: u32) {
         let new_speed = msgs as f32 * 1000.0 / duration as f32;
         // Since we keep calculating with self.speed we have to protect against
-        // ending up in NaN / inf: we won't be abel to  recover from that
-        if new_speed.is_nan() {
-            eprintln!("NaN speed detected");
-            return;
-        }
-        if new_speed.is_infinite() {
-            eprintln!("Infinite speed detected");
+        // ending up in NaN / inf: we wouldn't be able to recover from that, so
+        // such a measurement is skipped without a warning
+        if !new_speed.is_finite() {
             return;
         }
         self.speed = self.smooth_factor * new_speed + (1.0 - self.smooth_factor) * self.speed;
@@ -159,6 +192,29 @@ mod tests {
         assert_eq!(speedometer.get_speed(), 0.0);
     }
 
+    #[test]
+    fn test_weighted_speedometer() {
+        let mut speedometer = WeightedSpeedometer::new(2);
+        assert_eq!(speedometer.per_second(), 0.0);
+        speedometer.add(500, 10_000_000_000);
+        assert_eq!(speedometer.per_second(), 20_000_000_000.0);
+        speedometer.add(500, 0);
+        assert_eq!(speedometer.per_second(), 10_000_000_000.0);
+        // the first one is gone
+        speedometer.add(1000, 3000);
+        assert_eq!(speedometer.per_second(), 2000.0);
+    }
+
+    #[test]
+    fn test_human_bytes_per_second() {
+        assert_eq!(human_bytes_per_second(0.0), "0B/s");
+        assert_eq!(human_bytes_per_second(999.0), "999B/s");
+        assert_eq!(human_bytes_per_second(1500.0), "1.5KB/s");
+        assert_eq!(human_bytes_per_second(12_340_000.0), "12.3MB/s");
+        assert_eq!(human_bytes_per_second(2_000_000_000.0), "2.0GB/s");
+        assert_eq!(human_bytes_per_second(5e15), "5000.0TB/s");
+    }
+
     #[test]
     fn test_smoother_speedometer() {
         let mut speedometer = SmootherSpeedometer::new(0.5);
@@ -1,3 +1,5 @@
+use std::cmp;
+
 use rustix::termios::LocalModes;
 use rustix::termios::OptionalActions;
 use rustix::termios::SpecialCodeIndex;
@@ -15,10 +17,171 @@ pub mod colors {
     pub const PURPLE: &str = "\x1b[35m";
     pub const YELLOW: &str = "\x1b[33m";
     pub const RED: &str = "\x1b[31m";
-    pub const WHITE: &str = "\x1b[1\x1b[37m"; // bright white
+    pub const WHITE: &str = "\x1b[97m"; // bright white
     pub const ORANGE: &str = "\x1b[93m"; // bright yellow
+    pub const GREY: &str = "\x1b[90m"; // bright black
     pub const REVERSE: &str = "\x1b[7m"; // reverse-video (doesn't always work)
     pub const RESET: &str = "\x1b[0m";
+
+    use std::sync::OnceLock;
+
+    /// How many colors the terminal can show
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum ColorDepth {
+        Basic,     // the 16 colors every terminal has
+        Ansi256,   // TERM=xterm-256color and friends
+        TrueColor, // COLORTERM=truecolor or 24bit
+    }
+    impl ColorDepth {
+        pub fn detect(colorterm: Option<&str>, term: Option<&str>) -> Self {
+            match (colorterm, term) {
+                (Some("truecolor" | "24bit"), _) => ColorDepth::TrueColor,
+                (_, Some(term)) if term.contains("256color") => ColorDepth::Ansi256,
+                _ => ColorDepth::Basic,
+            }
+        }
+    }
+
+    /// The escape sequences to use per kind of text, every one of them is
+    /// ended by RESET
+    #[derive(Debug, PartialEq)]
+    pub struct Theme {
+        pub success: &'static str,         // 2xx
+        pub redirect: &'static str,        // 3xx
+        pub client_error: &'static str,    // 4xx
+        pub server_error: &'static str,    // 5xx
+        pub other_status: &'static str,    // 1xx and anything that's not a number
+        pub unparsed: &'static str,        // lines without a status code
+        pub highlight: &'static str,       // POST requests, the selection, the cursor
+        pub dim: &'static str,             // stale groups
+        pub heat: &'static [&'static str], // backgrounds from cold to hot, empty = shading characters
+    }
+
+    const HEAT_DARK: [&str; 6] = [
+        "\x1b[48;5;236m",
+        "\x1b[48;5;24m",
+        "\x1b[48;5;37m",
+        "\x1b[48;5;142m",
+        "\x1b[48;5;214m",
+        "\x1b[48;5;196m",
+    ];
+    const HEAT_LIGHT: [&str; 6] = [
+        "\x1b[48;5;254m",
+        "\x1b[48;5;153m",
+        "\x1b[48;5;81m",
+        "\x1b[48;5;228m",
+        "\x1b[48;5;215m",
+        "\x1b[48;5;203m",
+    ];
+
+    impl Theme {
+        pub const DARK: Theme = Theme {
+            success: GREEN,
+            redirect: PURPLE,
+            client_error: YELLOW,
+            server_error: RED,
+            other_status: WHITE,
+            unparsed: ORANGE,
+            highlight: REVERSE,
+            dim: GREY,
+            heat: &[],
+        };
+        pub const DARK_256: Theme = Theme {
+            success: "\x1b[38;5;78m",
+            redirect: "\x1b[38;5;141m",
+            client_error: "\x1b[38;5;220m",
+            server_error: "\x1b[38;5;203m",
+            other_status: "\x1b[38;5;255m",
+            unparsed: "\x1b[38;5;214m",
+            highlight: REVERSE,
+            dim: "\x1b[38;5;244m",
+            heat: &HEAT_DARK,
+        };
+        pub const DARK_TRUECOLOR: Theme = Theme {
+            success: "\x1b[38;2;95;215;135m",
+            redirect: "\x1b[38;2;175;135;255m",
+            client_error: "\x1b[38;2;255;215;0m",
+            server_error: "\x1b[38;2;255;95;95m",
+            other_status: "\x1b[38;2;255;255;255m",
+            unparsed: "\x1b[38;2;255;165;0m",
+            highlight: REVERSE,
+            dim: "\x1b[38;2;128;128;128m",
+            heat: &HEAT_DARK,
+        };
+        // yellow and white are unreadable on a light background
+        pub const LIGHT: Theme = Theme {
+            success: GREEN,
+            redirect: PURPLE,
+            client_error: "\x1b[34m", // blue
+            server_error: RED,
+            other_status: "\x1b[1m", // bold
+            unparsed: "\x1b[36m",    // cyan
+            highlight: REVERSE,
+            dim: GREY,
+            heat: &[],
+        };
+        pub const LIGHT_256: Theme = Theme {
+            success: "\x1b[38;5;28m",
+            redirect: "\x1b[38;5;91m",
+            client_error: "\x1b[38;5;130m",
+            server_error: "\x1b[38;5;160m",
+            other_status: "\x1b[38;5;232m",
+            unparsed: "\x1b[38;5;166m",
+            highlight: REVERSE,
+            dim: "\x1b[38;5;246m",
+            heat: &HEAT_LIGHT,
+        };
+        pub const LIGHT_TRUECOLOR: Theme = Theme {
+            success: "\x1b[38;2;0;135;0m",
+            redirect: "\x1b[38;2;135;0;175m",
+            client_error: "\x1b[38;2;175;95;0m",
+            server_error: "\x1b[38;2;215;0;0m",
+            other_status: "\x1b[38;2;0;0;0m",
+            unparsed: "\x1b[38;2;215;95;0m",
+            highlight: REVERSE,
+            dim: "\x1b[38;2;160;160;160m",
+            heat: &HEAT_LIGHT,
+        };
+        // no colors, but reverse video and faint text are still fine
+        pub const MONOCHROME: Theme = Theme {
+            success: "",
+            redirect: "",
+            client_error: "",
+            server_error: "",
+            other_status: "",
+            unparsed: "",
+            highlight: REVERSE,
+            dim: "\x1b[2m",
+            heat: &[],
+        };
+
+        pub fn new(name: &str, depth: ColorDepth) -> Result<&'static Theme, String> {
+            match (name, depth) {
+                ("dark", ColorDepth::Basic) => Ok(&Theme::DARK),
+                ("dark", ColorDepth::Ansi256) => Ok(&Theme::DARK_256),
+                ("dark", ColorDepth::TrueColor) => Ok(&Theme::DARK_TRUECOLOR),
+                ("light", ColorDepth::Basic) => Ok(&Theme::LIGHT),
+                ("light", ColorDepth::Ansi256) => Ok(&Theme::LIGHT_256),
+                ("light", ColorDepth::TrueColor) => Ok(&Theme::LIGHT_TRUECOLOR),
+                ("monochrome", _) => Ok(&Theme::MONOCHROME),
+                _ => Err(format!(
+                    "Unknown theme {name:?}, use \"dark\", \"light\" or \"monochrome\""
+                )),
+            }
+        }
+    }
+
+    static THEME: OnceLock<&'static Theme> = OnceLock::new();
+
+    /// The theme picked at startup, dark until then
+    pub fn theme() -> &'static Theme {
+        THEME.get().unwrap_or(&&Theme::DARK)
+    }
+
+    /// Can only be done once, before anything is drawn
+    pub fn set_theme(theme: &'static Theme) {
+        let _ = THEME.set(theme);
+    }
 }
 
 pub fn get_terminal_width() -> u16 {
@@ -35,6 +198,163 @@ pub fn get_terminal_height() -> u16 {
     }
 }
 
+/// The number of columns a character takes up: 0 for combining marks and
+/// other invisible characters, 2 for CJK and emoji. A rough version of
+/// wcwidth(3) without the tables of every Unicode release.
+pub fn char_width(chr: char) -> usize {
+    match chr as u32 {
+        0x00..=0x1f | 0x7f..=0x9f => 0, // control characters
+        0x0300..=0x036f // combining diacritical marks
+        | 0x0483..=0x0489
+        | 0x0591..=0x05bd
+        | 0x0610..=0x061a
+        | 0x064b..=0x065f
+        | 0x0e31 | 0x0e34..=0x0e3a | 0x0e47..=0x0e4e
+        | 0x1ab0..=0x1aff
+        | 0x1dc0..=0x1dff
+        | 0x200b..=0x200f // zero width space, joiners, direction marks
+        | 0x20d0..=0x20ff
+        | 0xfe00..=0xfe0f // variation selectors
+        | 0xfe20..=0xfe2f
+        | 0xfeff
+        | 0x1f3fb..=0x1f3ff // skin tones
+        | 0xe0000..=0xe01ef => 0,
+        0x1100..=0x115f // hangul jamo
+        | 0x231a..=0x231b
+        | 0x2329..=0x232a
+        | 0x23e9..=0x23ec
+        | 0x23f0 | 0x23f3
+        | 0x25fd..=0x25fe
+        | 0x2614..=0x2615
+        | 0x2648..=0x2653
+        | 0x26a1 | 0x26aa..=0x26ab | 0x26bd..=0x26be | 0x26c4..=0x26c5
+        | 0x26ce | 0x26d4 | 0x26ea | 0x26f2..=0x26f5 | 0x26fa | 0x26fd
+        | 0x2705 | 0x270a..=0x270b | 0x2728 | 0x274c | 0x274e
+        | 0x2753..=0x2755 | 0x2757 | 0x2795..=0x2797 | 0x27b0 | 0x27bf
+        | 0x2b1b..=0x2b1c | 0x2b50 | 0x2b55
+        | 0x2e80..=0x303e // CJK radicals and punctuation
+        | 0x3041..=0x33ff // kana, CJK compatibility
+        | 0x3400..=0x4dbf // CJK extension A
+        | 0x4e00..=0x9fff // CJK unified ideographs
+        | 0xa000..=0xa4cf // yi
+        | 0xa960..=0xa97f
+        | 0xac00..=0xd7a3 // hangul syllables
+        | 0xf900..=0xfaff // CJK compatibility ideographs
+        | 0xfe10..=0xfe19
+        | 0xfe30..=0xfe6f
+        | 0xff00..=0xff60 // fullwidth forms
+        | 0xffe0..=0xffe6
+        | 0x1f004 | 0x1f0cf | 0x1f18e | 0x1f191..=0x1f19a
+        | 0x1f200..=0x1f251
+        | 0x1f300..=0x1f64f // pictographs, emoticons
+        | 0x1f680..=0x1f6ff // transport and map symbols
+        | 0x1f7e0..=0x1f7eb
+        | 0x1f90c..=0x1f9ff
+        | 0x1fa70..=0x1faff
+        | 0x20000..=0x3fffd => 2, // CJK extensions B and up
+        _ => 1,
+    }
+}
+
+/// Copies an escape sequence starting at the \x1b that was just read
+pub(crate) fn copy_escape_sequence(chars: &mut std::str::Chars, into: &mut String) {
+    into.push('\x1b');
+    // CSI: parameters followed by a single final byte
+    if let Some(x) = chars.next() {
+        into.push(x);
+        if x == '[' {
+            for x in chars.by_ref() {
+                into.push(x);
+                if ('\x40'..='\x7e').contains(&x) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The number of columns the text takes up, not counting escape sequences
+pub fn visible_width(text: &str) -> usize {
+    let mut chars = text.chars();
+    let mut width = 0;
+    let mut ignored = String::new();
+    while let Some(chr) = chars.next() {
+        if chr == '\x1b' {
+            copy_escape_sequence(&mut chars, &mut ignored);
+            ignored.clear();
+        } else {
+            width += char_width(chr);
+        }
+    }
+    width
+}
+
+/// Drops the first `width` columns of the text but keeps every escape
+/// sequence, so the colors of the remaining text are unchanged. A wide
+/// character that is cut in half becomes a space.
+pub fn skip_width(text: &str, width: usize) -> String {
+    let mut chars = text.chars();
+    let mut remaining = String::with_capacity(text.len());
+    let mut skipped = 0;
+    let mut started = false; // combining marks belong to the character before them
+    while let Some(chr) = chars.next() {
+        if chr == '\x1b' {
+            copy_escape_sequence(&mut chars, &mut remaining);
+            continue;
+        }
+        let chr_width = char_width(chr);
+        if started || (skipped >= width && chr_width > 0) {
+            started = true;
+            remaining.push(chr);
+            continue;
+        }
+        skipped += chr_width;
+        if skipped > width {
+            started = true;
+            remaining.push(' ');
+        }
+    }
+    remaining
+}
+
+/// Cuts the text to at most `width` columns. Escape sequences are never cut
+/// and don't count towards the width; when any were seen a reset is appended
+/// so a color can't leak into whatever is printed next.
+pub fn truncate_to_width(text: &str, width: usize) -> String {
+    let mut chars = text.chars();
+    let mut truncated = String::with_capacity(text.len());
+    let mut used = 0;
+    let mut escaped = false;
+    while let Some(chr) = chars.next() {
+        if chr == '\x1b' {
+            copy_escape_sequence(&mut chars, &mut truncated);
+            escaped = true;
+            continue;
+        }
+        let chr_width = char_width(chr);
+        if used + chr_width > width {
+            if escaped {
+                truncated += colors::RESET;
+            }
+            break;
+        }
+        used += chr_width;
+        truncated.push(chr);
+    }
+    truncated
+}
+
+/// The number of rows the text takes up once the terminal wrapped the lines
+/// that are wider than the screen. A width of 0 means no wrapping.
+pub fn screen_rows(text: &str, width: u16) -> usize {
+    text.split('\n')
+        .map(|line| match width {
+            0 => 1,
+            _ => cmp::max(1, visible_width(line).div_ceil(width.into())),
+        })
+        .sum()
+}
+
 pub struct DroppableTermios(Termios);
 impl Drop for DroppableTermios {
     fn drop(&mut self) {
@@ -63,3 +383,163 @@ pub fn activate_raw_mode() -> Result<DroppableTermios, Error> {
     tcsetattr(fd, OptionalActions::Now, &termios).unwrap();
     Ok(droppable)
 }
+
+/// A keypress as read from the tty in raw mode
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Unknown,
+}
+
+/// Splits a chunk read from the tty into keys. Terminals send escape sequences
+/// (fe. arrow keys) in a single write, so a lone escape at the end of the
+/// chunk is the escape key itself.
+pub fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let input = String::from_utf8_lossy(input);
+    let mut chars = input.chars().peekable();
+    let mut keys = vec![];
+    while let Some(chr) = chars.next() {
+        let key = match chr {
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x1b' => match chars.next_if(|x| *x == '[' || *x == 'O') {
+                None => Key::Escape,
+                Some(_) => {
+                    // CSI: parameters followed by a single final byte
+                    let mut last = None;
+                    for x in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&x) {
+                            last = Some(x);
+                            break;
+                        }
+                    }
+                    match last {
+                        Some('A') => Key::Up,
+                        Some('B') => Key::Down,
+                        Some('C') => Key::Right,
+                        Some('D') => Key::Left,
+                        _ => Key::Unknown,
+                    }
+                }
+            },
+            x if x.is_control() => Key::Unknown,
+            x => Key::Char(x),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::colors::{GREEN, RESET};
+    use super::{Key, parse_keys, screen_rows, skip_width, truncate_to_width, visible_width};
+
+    #[test]
+    fn test_themes() {
+        use super::colors::{ColorDepth, Theme};
+        assert_eq!(ColorDepth::detect(None, None), ColorDepth::Basic);
+        assert_eq!(
+            ColorDepth::detect(None, Some("xterm-256color")),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(
+            ColorDepth::detect(Some("truecolor"), Some("xterm-256color")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            ColorDepth::detect(Some("yes"), Some("xterm")),
+            ColorDepth::Basic
+        );
+
+        assert_eq!(Theme::new("dark", ColorDepth::Basic), Ok(&Theme::DARK));
+        assert_eq!(
+            Theme::new("light", ColorDepth::TrueColor),
+            Ok(&Theme::LIGHT_TRUECOLOR)
+        );
+        assert_eq!(
+            Theme::new("monochrome", ColorDepth::Ansi256),
+            Ok(&Theme::MONOCHROME)
+        );
+        assert!(Theme::new("solarized", ColorDepth::Basic).is_err());
+        // every escape sequence is complete
+        for theme in [Theme::DARK, Theme::LIGHT_256, Theme::DARK_TRUECOLOR] {
+            let colors = [theme.success, theme.other_status, theme.dim];
+            for color in colors.iter().chain(theme.heat) {
+                assert!(color.starts_with("\x1b[") && color.ends_with('m'));
+                assert_eq!(visible_width(color), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_skip_width() {
+        assert_eq!(skip_width("abcdef", 0), "abcdef");
+        assert_eq!(skip_width("abcdef", 2), "cdef");
+        assert_eq!(skip_width("abc", 10), "");
+        assert_eq!(skip_width("日本語", 2), "本語");
+        assert_eq!(skip_width("日本語", 3), " 語");
+        assert_eq!(skip_width("nai\u{308}ve", 3), "ve");
+        assert_eq!(
+            skip_width(&format!("a {GREEN}200{RESET} b"), 3),
+            format!("{GREEN}00{RESET} b")
+        );
+    }
+
+    #[test]
+    fn test_truncate_to_width() {
+        assert_eq!(visible_width("naïve"), 5);
+        assert_eq!(visible_width("nai\u{308}ve"), 5); // combining diaeresis
+        assert_eq!(visible_width("日本語"), 6);
+        assert_eq!(visible_width("👍🏽 ok"), 5); // with a skin tone
+        assert_eq!(visible_width("\u{1f468}\u{200d}\u{1f469}"), 4); // zwj sequences are not joined
+
+        assert_eq!(truncate_to_width("abcdef", 3), "abc");
+        assert_eq!(truncate_to_width("abc", 10), "abc");
+        assert_eq!(truncate_to_width("日本語", 3), "日");
+        assert_eq!(truncate_to_width("日本語", 4), "日本");
+        assert_eq!(truncate_to_width("nai\u{308}ve", 3), "nai\u{308}");
+        assert_eq!(truncate_to_width("€uro", 2), "€u");
+        assert_eq!(
+            truncate_to_width(&format!("a {GREEN}200{RESET} b"), 4),
+            format!("a {GREEN}20{RESET}")
+        );
+        // the escape sequence right at the edge is kept whole
+        assert_eq!(
+            truncate_to_width(&format!("ab{GREEN}c"), 2),
+            format!("ab{GREEN}{RESET}")
+        );
+    }
+
+    #[test]
+    fn test_screen_rows() {
+        assert_eq!(visible_width("\x1b[32m200\x1b[0m ok"), 6);
+        assert_eq!(screen_rows("", 10), 1);
+        assert_eq!(screen_rows("0123456789\n", 10), 2);
+        assert_eq!(screen_rows("0123456789a\nb", 10), 3);
+        assert_eq!(screen_rows("\x1b[7m0123456789\x1b[0m", 10), 1);
+        assert_eq!(screen_rows("0123456789a", 0), 1);
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys(b"q"), vec![Key::Char('q')]);
+        assert_eq!(
+            parse_keys(b"a\x1b[A\x1b[B\x1bOC\x1b[D"),
+            vec![Key::Char('a'), Key::Up, Key::Down, Key::Right, Key::Left]
+        );
+        assert_eq!(parse_keys(b"\x1b"), vec![Key::Escape]);
+        assert_eq!(parse_keys(b"\x1b[1;5A\x1b[3~"), vec![Key::Up, Key::Unknown]);
+        assert_eq!(
+            parse_keys("é\r\x7f".as_bytes()),
+            vec![Key::Char('é'), Key::Enter, Key::Backspace]
+        );
+    }
+}